@@ -0,0 +1,712 @@
+use std::collections::{HashMap, VecDeque};
+use std::collections::hash_map::Entry;
+
+use ncurses::*;
+
+// Keys as the interpreter sees them, no matter where they were read from
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Key {
+    Char(char),
+    Enter,
+    Backspace,
+    Delete,
+    Tab,
+    BackTab,
+    Escape,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Resize,
+    Eof, // No more input will ever arrive
+}
+
+impl Key {
+    // The character a key stands for when it is stored as text (ACCEPT)
+    pub fn to_char(self) -> Option<char> {
+        match self {
+            Key::Char(c) => Some(c),
+            Key::Enter => Some('\n'),
+            Key::Tab => Some('\t'),
+            Key::Escape => Some('\x1b'),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Attribute {
+    Bold,
+    Dim,
+    Underline,
+    Blink,
+    Reverse,
+}
+
+impl Attribute {
+    pub const ALL: [Attribute; 5] = [Attribute::Bold, Attribute::Dim, Attribute::Underline, Attribute::Blink, Attribute::Reverse];
+
+    // Attributes are kept as a bit set in each cell of a VirtualScreen
+    pub fn bit(self) -> u8 {
+        match self {
+            Attribute::Bold => 1,
+            Attribute::Dim => 2,
+            Attribute::Underline => 4,
+            Attribute::Blink => 8,
+            Attribute::Reverse => 16,
+        }
+    }
+}
+
+// Everything the interpreter needs from a terminal. Colors follow the ncurses model:
+// numbered colors hold rgb values from 0 to 1000 and numbered pairs join two of them.
+pub trait Backend {
+    // Output
+    fn print(&mut self, text: &str);
+    fn delete_char(&mut self);
+    fn clear(&mut self);
+    fn refresh(&mut self);
+
+    // Cursor
+    fn move_cursor(&mut self, y: i32, x: i32);
+    fn cursor(&self) -> (i32, i32);
+    fn size(&self) -> (i32, i32);
+    fn set_cursor_visible(&mut self, visible: bool);
+
+    // Windows sharing the terminal: the size of the whole terminal, and moving this window to cover
+    // the given lines and columns from y, x. A backend that has the terminal to itself only resizes.
+    fn terminal_size(&self) -> (i32, i32);
+    fn place(&mut self, lines: i32, cols: i32, y: i32, x: i32);
+
+    // Scrolling. Within the lines from top to bottom a newline on the last one scrolls them up, and
+    // so does scroll, by as many lines as it is given. None stops scrolling the window.
+    fn set_scroll_region(&mut self, region: Option<(i32, i32)>);
+    fn scroll(&mut self, lines: i32);
+
+    // Color
+    fn define_color(&mut self, color: i16, r: i16, g: i16, b: i16);
+    fn define_pair(&mut self, pair: i16, foreground: i16, background: i16);
+    fn use_pair(&mut self, pair: i16);
+
+    // Attributes
+    fn attribute_on(&mut self, attribute: Attribute);
+    fn attribute_off(&mut self, attribute: Attribute);
+
+    // Input
+    fn read_key(&mut self) -> Key;
+    // Waits at most this many milliseconds for a key, returning None if none came
+    fn poll_key(&mut self, timeout: i32) -> Option<Key>;
+
+    // Handing the terminal to another program and taking it back, redrawing the screen
+    fn suspend(&mut self);
+    fn resume(&mut self);
+
+    // A copy of everything currently on the screen, colors included
+    fn snapshot(&self) -> VirtualScreen;
+}
+
+pub struct NcursesBackend {
+    window: WINDOW,
+    owner: bool, // Whether dropping it leaves curses mode, rather than only removing its window
+}
+
+impl NcursesBackend {
+    // Puts the terminal into curses mode until the backend is dropped
+    pub fn new() -> NcursesBackend {
+        initscr();
+        curs_set(CURSOR_VISIBILITY::CURSOR_INVISIBLE);
+        cbreak();
+        keypad(stdscr(), true);
+        noecho();
+
+        if has_colors() && can_change_color() {
+            start_color();
+        }
+
+        clear();
+
+        NcursesBackend {
+            window: stdscr(),
+            owner: true,
+        }
+    }
+
+    // A window of its own, for dividing the terminal between backends. Curses mode has to be
+    // started by NcursesBackend::new first and kept until the window is dropped.
+    pub fn window(lines: i32, cols: i32, y: i32, x: i32) -> NcursesBackend {
+        let window = newwin(lines, cols, y, x);
+        keypad(window, true);
+
+        NcursesBackend {
+            window,
+            owner: false,
+        }
+    }
+}
+
+impl Default for NcursesBackend {
+    fn default() -> NcursesBackend {
+        NcursesBackend::new()
+    }
+}
+
+impl Drop for NcursesBackend {
+    fn drop(&mut self) {
+        if self.owner {
+            endwin();
+        } else {
+            delwin(self.window);
+        }
+    }
+}
+
+fn attribute_flag(attribute: Attribute) -> attr_t {
+    match attribute {
+        Attribute::Bold => A_BOLD(),
+        Attribute::Dim => A_DIM(),
+        Attribute::Underline => A_UNDERLINE(),
+        Attribute::Blink => A_BLINK(),
+        Attribute::Reverse => A_REVERSE(),
+    }
+}
+
+impl Backend for NcursesBackend {
+    fn print(&mut self, text: &str) {
+        waddstr(self.window, text);
+    }
+
+    fn delete_char(&mut self) {
+        wdelch(self.window);
+    }
+
+    fn clear(&mut self) {
+        wclear(self.window);
+    }
+
+    fn refresh(&mut self) {
+        wrefresh(self.window);
+    }
+
+    fn move_cursor(&mut self, y: i32, x: i32) {
+        wmove(self.window, y, x);
+    }
+
+    fn cursor(&self) -> (i32, i32) {
+        let mut y = 0;
+        let mut x = 0;
+        getyx(self.window, &mut y, &mut x);
+        (y, x)
+    }
+
+    fn size(&self) -> (i32, i32) {
+        let mut lines = 0;
+        let mut cols = 0;
+        getmaxyx(self.window, &mut lines, &mut cols);
+        (lines, cols)
+    }
+
+    fn set_cursor_visible(&mut self, visible: bool) {
+        if visible {
+            curs_set(CURSOR_VISIBILITY::CURSOR_VISIBLE);
+        } else {
+            curs_set(CURSOR_VISIBILITY::CURSOR_INVISIBLE);
+        }
+    }
+
+    fn define_color(&mut self, color: i16, r: i16, g: i16, b: i16) {
+        init_color(color, r, g, b);
+    }
+
+    fn define_pair(&mut self, pair: i16, foreground: i16, background: i16) {
+        init_pair(pair, foreground, background);
+    }
+
+    fn use_pair(&mut self, pair: i16) {
+        wbkgd(self.window, ' ' as chtype | COLOR_PAIR(pair) as chtype);
+        wattron(self.window, COLOR_PAIR(pair));
+    }
+
+    fn attribute_on(&mut self, attribute: Attribute) {
+        wattron(self.window, attribute_flag(attribute));
+    }
+
+    fn attribute_off(&mut self, attribute: Attribute) {
+        wattroff(self.window, attribute_flag(attribute));
+    }
+
+    fn read_key(&mut self) -> Key {
+        loop {
+            let key = wgetch(self.window);
+            return match key {
+                ERR => Key::Eof,
+                KEY_RESIZE => Key::Resize,
+                KEY_ENTER | 10 | 13 => Key::Enter,
+                KEY_BACKSPACE | 127 | 8 => Key::Backspace,
+                KEY_DC => Key::Delete,
+                9 => Key::Tab,
+                KEY_BTAB => Key::BackTab,
+                27 => Key::Escape,
+                KEY_UP => Key::Up,
+                KEY_DOWN => Key::Down,
+                KEY_LEFT => Key::Left,
+                KEY_RIGHT => Key::Right,
+                KEY_HOME => Key::Home,
+                KEY_END => Key::End,
+                KEY_PPAGE => Key::PageUp,
+                KEY_NPAGE => Key::PageDown,
+                32..=126 => Key::Char(key as u8 as char),
+                // The leading byte of a UTF-8 sequence says how many bytes follow it
+                0xc0..=0xf7 => {
+                    let following = if key >= 0xf0 { 3 } else if key >= 0xe0 { 2 } else { 1 };
+                    let mut bytes = vec![key as u8];
+                    for _ in 0..following {
+                        bytes.push(wgetch(self.window) as u8);
+                    }
+                    match String::from_utf8_lossy(&bytes).chars().next() {
+                        Some(c) => Key::Char(c),
+                        None => continue,
+                    }
+                },
+                _ => continue,
+            };
+        }
+    }
+
+    // Without a timeout ERR means no more input, but with one it only means no key came in time
+    fn poll_key(&mut self, timeout: i32) -> Option<Key> {
+        wtimeout(self.window, timeout);
+        let key = self.read_key();
+        wtimeout(self.window, -1);
+        if key == Key::Eof { None } else { Some(key) }
+    }
+
+    fn terminal_size(&self) -> (i32, i32) {
+        let mut lines = 0;
+        let mut cols = 0;
+        getmaxyx(stdscr(), &mut lines, &mut cols);
+        (lines, cols)
+    }
+
+    // Curses resizes the standard screen itself, so only windows are moved. The window is resized
+    // first, since it has to fit in the terminal wherever it goes.
+    fn place(&mut self, lines: i32, cols: i32, y: i32, x: i32) {
+        if !self.owner {
+            wresize(self.window, lines, cols);
+            mvwin(self.window, y, x);
+        }
+    }
+
+    fn set_scroll_region(&mut self, region: Option<(i32, i32)>) {
+        match region {
+            Some((top, bottom)) => {
+                scrollok(self.window, true);
+                wsetscrreg(self.window, top, bottom);
+            },
+            None => {
+                wsetscrreg(self.window, 0, self.size().0 - 1);
+                scrollok(self.window, false);
+            },
+        }
+    }
+
+    fn scroll(&mut self, lines: i32) {
+        wscrl(self.window, lines);
+    }
+
+    // Curses mode is left for the whole terminal, whichever window does it
+    fn suspend(&mut self) {
+        def_prog_mode();
+        endwin();
+    }
+
+    fn resume(&mut self) {
+        reset_prog_mode();
+        redrawwin(self.window);
+        wrefresh(self.window);
+    }
+
+    fn snapshot(&self) -> VirtualScreen {
+        let (lines, cols) = self.size();
+        let mut res = VirtualScreen::new(lines, cols);
+
+        // Reading a cell moves the cursor, so it is put back afterwards
+        let (cursor_y, cursor_x) = self.cursor();
+        for y in 0..lines {
+            for x in 0..cols {
+                let ch = mvwinch(self.window, y, x);
+                let pair = PAIR_NUMBER(ch as i32) as i16;
+                let mut attributes = 0;
+                for attribute in Attribute::ALL.iter() {
+                    if ch & attribute_flag(*attribute) != 0 {
+                        attributes |= attribute.bit();
+                    }
+                }
+                let c = (ch & A_CHARTEXT()) as u8 as char;
+                res.set_cell(y, x, Cell { ch: c, pair, attributes });
+
+                if let Entry::Vacant(entry) = res.pairs.entry(pair) {
+                    let mut foreground = 0;
+                    let mut background = 0;
+                    pair_content(pair, &mut foreground, &mut background);
+                    entry.insert((foreground, background));
+                    for color in [foreground, background].iter() {
+                        let mut r = 0;
+                        let mut g = 0;
+                        let mut b = 0;
+                        color_content(*color, &mut r, &mut g, &mut b);
+                        res.colors.insert(*color, (r, g, b));
+                    }
+                }
+            }
+        }
+        wmove(self.window, cursor_y, cursor_x);
+        res.cursor = (cursor_y, cursor_x);
+
+        res
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub struct Cell {
+    pub ch: char,
+    pub pair: i16,
+    pub attributes: u8,
+}
+
+impl Cell {
+    pub fn has(&self, attribute: Attribute) -> bool {
+        self.attributes & attribute.bit() != 0
+    }
+}
+
+// An in-memory terminal that records every cell, so programs can run without a real one.
+// Input comes from a queue of keys; once it is empty every read returns Key::Eof.
+#[derive(Clone)]
+pub struct VirtualScreen {
+    lines: i32,
+    cols: i32,
+    cells: Vec<Vec<Cell>>,
+    cursor: (i32, i32),
+    cursor_visible: bool,
+    colors: HashMap<i16, (i16, i16, i16)>,
+    pairs: HashMap<i16, (i16, i16)>,
+    background: i16,
+    pair: i16,
+    attributes: u8,
+    keys: VecDeque<Key>,
+    region: Option<(i32, i32)>, // The lines that scroll, if any do
+}
+
+impl VirtualScreen {
+    pub fn new(lines: i32, cols: i32) -> VirtualScreen {
+        let mut colors = HashMap::new();
+        // The eight colors ncurses starts with
+        colors.insert(COLOR_BLACK, (0, 0, 0));
+        colors.insert(COLOR_RED, (680, 0, 0));
+        colors.insert(COLOR_GREEN, (0, 680, 0));
+        colors.insert(COLOR_YELLOW, (680, 680, 0));
+        colors.insert(COLOR_BLUE, (0, 0, 680));
+        colors.insert(COLOR_MAGENTA, (680, 0, 680));
+        colors.insert(COLOR_CYAN, (0, 680, 680));
+        colors.insert(COLOR_WHITE, (680, 680, 680));
+
+        let mut pairs = HashMap::new();
+        pairs.insert(0, (COLOR_WHITE, COLOR_BLACK));
+
+        VirtualScreen {
+            lines,
+            cols,
+            cells: vec![vec![Cell { ch: ' ', pair: 0, attributes: 0 }; cols as usize]; lines as usize],
+            cursor: (0, 0),
+            cursor_visible: true,
+            colors,
+            pairs,
+            background: 0,
+            pair: 0,
+            attributes: 0,
+            keys: VecDeque::new(),
+            region: None,
+        }
+    }
+
+    pub fn push_key(&mut self, key: Key) {
+        self.keys.push_back(key);
+    }
+
+    pub fn push_keys<I: IntoIterator<Item = Key>>(&mut self, keys: I) {
+        self.keys.extend(keys);
+    }
+
+    // Types out text as if each character had been pressed in turn
+    pub fn push_str(&mut self, text: &str) {
+        for c in text.chars() {
+            self.push_key(match c {
+                '\n' => Key::Enter,
+                '\t' => Key::Tab,
+                _ => Key::Char(c),
+            });
+        }
+    }
+
+    // Changes the size the way a terminal emulator would and queues the KEY_RESIZE that follows
+    pub fn resize(&mut self, lines: i32, cols: i32) {
+        self.reshape(lines, cols);
+        self.push_key(Key::Resize);
+    }
+
+    fn reshape(&mut self, lines: i32, cols: i32) {
+        let blank = self.blank();
+        self.cells.resize(lines as usize, vec![blank; cols as usize]);
+        for row in self.cells.iter_mut() {
+            row.resize(cols as usize, blank);
+        }
+        self.lines = lines;
+        self.cols = cols;
+        self.cursor = (self.cursor.0.min(lines - 1), self.cursor.1.min(cols - 1));
+        self.region = self.region.filter(|(_, bottom)| *bottom < lines);
+    }
+
+    pub fn cell(&self, y: i32, x: i32) -> Cell {
+        self.cells[y as usize][x as usize]
+    }
+
+    pub fn set_cell(&mut self, y: i32, x: i32, cell: Cell) {
+        self.cells[y as usize][x as usize] = cell;
+    }
+
+    // A row of text with its trailing blanks removed
+    pub fn row(&self, y: i32) -> String {
+        let row: String = self.cells[y as usize].iter().map(|cell| cell.ch).collect();
+        row.trim_end().to_owned()
+    }
+
+    // Every row joined by newlines, with trailing blank rows removed
+    pub fn text(&self) -> String {
+        let rows: Vec<String> = (0..self.lines).map(|y| self.row(y)).collect();
+        rows.join("\n").trim_end().to_owned()
+    }
+
+    pub fn is_cursor_visible(&self) -> bool {
+        self.cursor_visible
+    }
+
+    pub fn color(&self, color: i16) -> (i16, i16, i16) {
+        *self.colors.get(&color).unwrap_or(&(0, 0, 0))
+    }
+
+    pub fn pair(&self, pair: i16) -> (i16, i16) {
+        *self.pairs.get(&pair).unwrap_or(&(COLOR_WHITE, COLOR_BLACK))
+    }
+
+    // The rgb values a cell is currently shown in, as (foreground, background)
+    pub fn cell_colors(&self, y: i32, x: i32) -> ((i16, i16, i16), (i16, i16, i16)) {
+        let (foreground, background) = self.pair(self.cell(y, x).pair);
+        (self.color(foreground), self.color(background))
+    }
+
+    fn blank(&self) -> Cell {
+        Cell { ch: ' ', pair: self.background, attributes: 0 }
+    }
+}
+
+impl Backend for VirtualScreen {
+    fn print(&mut self, text: &str) {
+        for c in text.chars() {
+            let (y, x) = self.cursor;
+            // Like ncurses without scrolling, nothing is written past the last line
+            if y >= self.lines {
+                return;
+            }
+
+            if c == '\n' {
+                for column in x..self.cols {
+                    self.cells[y as usize][column as usize] = self.blank();
+                }
+                match self.region {
+                    Some((_, bottom)) if y == bottom => {
+                        self.scroll(1);
+                        self.cursor = (y, 0);
+                    },
+                    _ => self.cursor = (y + 1, 0),
+                }
+                continue;
+            }
+
+            self.cells[y as usize][x as usize] = Cell { ch: c, pair: self.pair, attributes: self.attributes };
+            if x + 1 < self.cols {
+                self.cursor = (y, x + 1);
+            } else if y + 1 < self.lines {
+                self.cursor = (y + 1, 0);
+            } else {
+                return;
+            }
+        }
+    }
+
+    fn delete_char(&mut self) {
+        let (y, x) = self.cursor;
+        let blank = self.blank();
+        let row = &mut self.cells[y as usize];
+        row.remove(x as usize);
+        row.push(blank);
+    }
+
+    fn clear(&mut self) {
+        let blank = self.blank();
+        for row in self.cells.iter_mut() {
+            for cell in row.iter_mut() {
+                *cell = blank;
+            }
+        }
+        self.cursor = (0, 0);
+    }
+
+    fn refresh(&mut self) {}
+
+    fn move_cursor(&mut self, y: i32, x: i32) {
+        // ncurses refuses to move outside of the window
+        if y >= 0 && y < self.lines && x >= 0 && x < self.cols {
+            self.cursor = (y, x);
+        }
+    }
+
+    fn cursor(&self) -> (i32, i32) {
+        self.cursor
+    }
+
+    fn size(&self) -> (i32, i32) {
+        (self.lines, self.cols)
+    }
+
+    fn set_cursor_visible(&mut self, visible: bool) {
+        self.cursor_visible = visible;
+    }
+
+    fn terminal_size(&self) -> (i32, i32) {
+        (self.lines, self.cols)
+    }
+
+    fn place(&mut self, lines: i32, cols: i32, _y: i32, _x: i32) {
+        self.reshape(lines, cols);
+    }
+
+    // Like ncurses, a region that does not fit is refused
+    fn set_scroll_region(&mut self, region: Option<(i32, i32)>) {
+        match region {
+            Some((top, bottom)) if top < 0 || bottom >= self.lines || top > bottom => {},
+            _ => self.region = region,
+        }
+    }
+
+    // Lines scrolled out are gone, and the ones scrolled in are blank; a negative count scrolls down
+    fn scroll(&mut self, lines: i32) {
+        let (top, bottom) = match self.region {
+            Some(region) => region,
+            None => return,
+        };
+        let blank = vec![self.blank(); self.cols as usize];
+        let rows = &mut self.cells[top as usize..=bottom as usize];
+        let height = rows.len();
+        let count = (lines.unsigned_abs() as usize).min(height);
+        if lines > 0 {
+            rows.rotate_left(count);
+            for row in rows[height - count..].iter_mut() {
+                *row = blank.clone();
+            }
+        } else {
+            rows.rotate_right(count);
+            for row in rows[..count].iter_mut() {
+                *row = blank.clone();
+            }
+        }
+    }
+
+    fn define_color(&mut self, color: i16, r: i16, g: i16, b: i16) {
+        self.colors.insert(color, (r, g, b));
+    }
+
+    fn define_pair(&mut self, pair: i16, foreground: i16, background: i16) {
+        self.pairs.insert(pair, (foreground, background));
+    }
+
+    fn use_pair(&mut self, pair: i16) {
+        // Changing the background recolors every blank cell, as bkgd does
+        let old = self.background;
+        for row in self.cells.iter_mut() {
+            for cell in row.iter_mut() {
+                if cell.pair == old {
+                    cell.pair = pair;
+                }
+            }
+        }
+        self.background = pair;
+        self.pair = pair;
+    }
+
+    fn attribute_on(&mut self, attribute: Attribute) {
+        self.attributes |= attribute.bit();
+    }
+
+    fn attribute_off(&mut self, attribute: Attribute) {
+        self.attributes &= !attribute.bit();
+    }
+
+    fn read_key(&mut self) -> Key {
+        self.keys.pop_front().unwrap_or(Key::Eof)
+    }
+
+    // Keys are either queued already or never come
+    fn poll_key(&mut self, _timeout: i32) -> Option<Key> {
+        Some(self.read_key())
+    }
+
+    // There is no terminal to hand over, so a program run in between gets the real one
+    fn suspend(&mut self) {}
+
+    fn resume(&mut self) {}
+
+    fn snapshot(&self) -> VirtualScreen {
+        self.clone()
+    }
+}
+
+#[test]
+fn virtual_screen_test() {
+    let mut screen = VirtualScreen::new(3, 5);
+    screen.print("hello world");
+    assert_eq!(screen.text(), "hello\n worl\nd");
+    assert_eq!(screen.cursor(), (2, 1));
+
+    screen.move_cursor(0, 1);
+    screen.delete_char();
+    assert_eq!(screen.row(0), "hllo");
+
+    screen.attribute_on(Attribute::Bold);
+    screen.move_cursor(1, 0);
+    screen.print("A");
+    screen.attribute_off(Attribute::Bold);
+    assert!(screen.cell(1, 0).has(Attribute::Bold));
+    assert!(!screen.cell(1, 1).has(Attribute::Bold));
+
+    // Only the lines of the region scroll
+    let mut screen = VirtualScreen::new(4, 5);
+    screen.print("top\none\ntwo\nend");
+    screen.set_scroll_region(Some((1, 2)));
+    screen.move_cursor(2, 3);
+    screen.print("\nnew");
+    assert_eq!(screen.text(), "top\ntwo\nnew\nend");
+    screen.scroll(-1);
+    assert_eq!(screen.text(), "top\n\ntwo\nend");
+    screen.set_scroll_region(None);
+    screen.scroll(1);
+    assert_eq!(screen.row(2), "two");
+
+    screen.push_str("a\n");
+    assert_eq!(screen.read_key(), Key::Char('a'));
+    assert_eq!(screen.read_key(), Key::Enter);
+    assert_eq!(screen.read_key(), Key::Eof);
+}
@@ -0,0 +1,672 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::diagnostic::{Diagnostic, Span};
+use crate::functions;
+use crate::parser::{ColorOperand, FieldKind, GaugeSource, Operand, Program, Statement, StatementKind, TailSource};
+use crate::snapshot::Format;
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+enum Type {
+    Num,
+    Str,
+    Clr,
+    List,
+    Map,
+    Bool,
+    Any, // Whatever a host function returned, which is only known once it runs
+}
+
+impl Type {
+    fn name(self) -> &'static str {
+        match self {
+            Type::Num => "number",
+            Type::Str => "string",
+            Type::Clr => "color",
+            Type::List => "list",
+            Type::Map => "map",
+            Type::Bool => "bool",
+            Type::Any => "value",
+        }
+    }
+
+    // The type a value is known to have before the program runs
+    fn of(operand: &Operand, types: &HashMap<String, Type>) -> Type {
+        match operand {
+            Operand::Literal(_, true, _) => Type::Str,
+            Operand::Literal(..) => Type::Num,
+            Operand::Identifier(name, _) => types.get(name).copied().unwrap_or(Type::Any),
+            Operand::List(..) => Type::List,
+            Operand::Map(..) => Type::Map,
+            Operand::Bool(..) | Operand::Compare(..) => Type::Bool,
+            Operand::Call(..) | Operand::Index(..) => Type::Any,
+        }
+    }
+}
+
+// Finds what would go wrong when a program runs, without running it: reads of variables that
+// were never assigned, operands of the wrong type, calls to unknown functions, handlers that can
+// never fire and code after a RETURN. Only the built-in functions are known.
+pub fn check(program: &Program) -> Vec<Diagnostic> {
+    check_with(program, &functions::builtin_names())
+}
+
+// The same, for a host that has registered functions of its own
+pub fn check_with(program: &Program, functions: &[String]) -> Vec<Diagnostic> {
+    let mut types = HashMap::new();
+    for name in ["x", "y", "G", "$"].iter() {
+        types.insert(name.to_string(), Type::Num);
+    }
+    types.insert("foreground".to_owned(), Type::Clr);
+    types.insert("background".to_owned(), Type::Clr);
+    types.insert("out".to_owned(), Type::Str);
+    types.insert("args".to_owned(), Type::List);
+
+    let mut checker = Checker {
+        types,
+        positions: HashSet::new(),
+        constants: HashSet::new(),
+        functions: functions.iter().cloned().collect(),
+        procedures: HashMap::new(),
+        depth: 0,
+        pending: None,
+        diagnostics: Vec::new(),
+    };
+
+    checker.statements(&program.statements);
+    checker.unreachable();
+
+    checker.diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+    checker.diagnostics
+}
+
+struct Checker {
+    types: HashMap<String, Type>,
+    positions: HashSet<String>,
+    constants: HashSet<String>,
+    functions: HashSet<String>,
+    procedures: HashMap<String, usize>, // How many parameters each procedure takes
+    depth: usize, // How many procedure bodies the statement being checked is inside
+    // A RESIZE handler that has been registered but that no input has given a chance to run yet
+    pending: Option<(Span, Vec<Statement>)>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker {
+    // Whatever follows a RETURN in the same block never runs; only the first such statement is
+    // reported, though all of them are still checked
+    fn statements(&mut self, statements: &[Statement]) {
+        let mut returned = false;
+        for statement in statements.iter() {
+            if returned {
+                self.diagnostics.push(Diagnostic::warning(statement.span, "Unreachable code: the procedure has always returned before this point".to_owned()));
+                returned = false;
+            }
+            self.statement(statement);
+            returned = returned || returns(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        let name = statement.operator.name();
+        match &statement.kind {
+            StatementKind::Print(value) => {
+                self.read(value, Type::Str, name);
+            },
+            StatementKind::Await(target) | StatementKind::Accept(target) => {
+                self.input();
+                self.assign(target, Type::Str);
+            },
+            StatementKind::Foreground(color) | StatementKind::Background(color) => {
+                match color {
+                    ColorOperand::Variable(value) => self.read(value, Type::Clr, name),
+                    ColorOperand::Rgb(r, g, b) => {
+                        for component in [r, g, b].iter() {
+                            self.component(component);
+                        }
+                    },
+                }
+            },
+            StatementKind::Move(line, column) | StatementKind::Shift(line, column) => {
+                self.read(line, Type::Num, name);
+                self.read(column, Type::Num, name);
+            },
+            StatementKind::StringAssign(target, value) => {
+                self.read(value, Type::Str, name);
+                self.declared(target, name);
+                self.assign(target, Type::Str);
+            },
+            StatementKind::NumberAssign(target, value) => {
+                self.read(value, Type::Num, name);
+                self.declared(target, name);
+                self.assign(target, Type::Num);
+            },
+            StatementKind::ColorAssign(target, r, g, b) => {
+                for component in [r, g, b].iter() {
+                    self.component(component);
+                }
+                self.assign(target, Type::Clr);
+            },
+            StatementKind::On(event, body) => {
+                if let Operand::Identifier(event, span) = event {
+                    if event != "RESIZE" {
+                        self.diagnostics.push(Diagnostic::error(*span, format!("Unknown event `{}` provided to ON operator", event)));
+                        return;
+                    }
+                }
+                // A handler replaced before any input was read could never have run
+                self.unreachable();
+                self.pending = Some((statement.span, body.clone()));
+            },
+            StatementKind::SavePosition(target) => {
+                if let Operand::Identifier(position, _) = target {
+                    self.positions.insert(position.to_owned());
+                }
+            },
+            StatementKind::RestorePosition(target) => {
+                if let Operand::Identifier(position, span) = target {
+                    if !self.positions.contains(position) {
+                        self.diagnostics.push(Diagnostic::error(*span, format!("Position `{}` is restored before it is ever saved", position)));
+                    }
+                }
+            },
+            StatementKind::Snapshot(path, format) => {
+                self.read(path, Type::Str, name);
+                if let Some(Operand::Identifier(format, span)) = format {
+                    if Format::from_name(format).is_none() {
+                        self.diagnostics.push(Diagnostic::error(*span, format!("Unknown format `{}` provided to SNAPSHOT operator", format)));
+                    }
+                }
+            },
+            StatementKind::Call(function, arguments, target) => {
+                if let Operand::Identifier(function, span) = function {
+                    self.function(function, arguments.len(), *span);
+                }
+                for argument in arguments.iter() {
+                    self.read(argument, Type::Any, name);
+                }
+                if let Some(target) = target {
+                    self.assign(target, Type::Any);
+                }
+            },
+            StatementKind::Proc(procedure, parameters, body) => {
+                if let Operand::Identifier(procedure, _) = procedure {
+                    self.procedures.insert(procedure.to_owned(), parameters.len());
+                }
+                // The body is checked where it is defined, with parameters of any type. What it
+                // assigns is local to it, so none of it is kept.
+                let types = self.types.clone();
+                let constants = self.constants.clone();
+                for parameter in parameters.iter() {
+                    if let Operand::Identifier(parameter, _) = parameter {
+                        self.types.insert(parameter.to_owned(), Type::Any);
+                    }
+                }
+                self.depth += 1;
+                self.statements(body);
+                self.depth -= 1;
+                self.types = types;
+                self.constants = constants;
+            },
+            StatementKind::ListAssign(target, value) => {
+                self.read(value, Type::List, name);
+                self.assign(target, Type::List);
+            },
+            StatementKind::Push(list, value) => {
+                self.read(list, Type::List, name);
+                self.writable(list);
+                self.read(value, Type::Any, name);
+            },
+            StatementKind::Pop(list, target) => {
+                self.read(list, Type::List, name);
+                self.writable(list);
+                self.assign(target, Type::Any);
+            },
+            StatementKind::BoolAssign(target, value) => {
+                // Any value can be taken as true or false
+                self.read(value, Type::Any, name);
+                self.assign(target, Type::Bool);
+            },
+            StatementKind::Confirm(target) => {
+                self.input();
+                self.assign(target, Type::Bool);
+            },
+            // Both branches are checked as if they both ran
+            StatementKind::If(condition, body, otherwise) => {
+                self.read(condition, Type::Any, name);
+                self.statements(body);
+                self.statements(otherwise);
+            },
+            StatementKind::MapAssign(target, value) => {
+                self.read(value, Type::Map, name);
+                self.assign(target, Type::Map);
+            },
+            // The body is checked as if the loop ran at least once
+            StatementKind::For(item, list, body) => {
+                let iterated = match list {
+                    Operand::Identifier(list, _) if self.types.get(list) == Some(&Type::Map) => Type::Map,
+                    Operand::Map(..) => Type::Map,
+                    _ => Type::List,
+                };
+                self.read(list, iterated, name);
+                self.redeclare(item, Type::Any);
+                self.statements(body);
+            },
+            // Inside a procedure a declaration may hide a global variable, so only declarations
+            // outside of one can be known to clash
+            StatementKind::Let(target, value) | StatementKind::Const(target, value) => {
+                self.read(value, Type::Any, name);
+                if let Operand::Identifier(variable, span) = target {
+                    if self.depth == 0 && self.types.contains_key(variable) && built_in(variable).is_none() {
+                        self.diagnostics.push(Diagnostic::error(*span, format!("`{}` is already declared", variable)));
+                        return;
+                    }
+                }
+                let found = Type::of(value, &self.types);
+                self.assign(target, found);
+                if let (StatementKind::Const(..), Operand::Identifier(variable, _)) = (&statement.kind, target) {
+                    self.constants.insert(variable.to_owned());
+                }
+            },
+            StatementKind::Env(target, variable, default) => {
+                self.read(variable, Type::Str, name);
+                if let Some(default) = default {
+                    self.read(default, Type::Str, name);
+                }
+                self.assign(target, Type::Str);
+            },
+            // A command is a string for the shell, or a list of the program and its arguments
+            StatementKind::Exec(target, command) => {
+                self.read(command, Type::Any, name);
+                self.assign(target, Type::Map);
+            },
+            StatementKind::Shell(target, command) => {
+                self.read(command, Type::Any, name);
+                self.assign(target, Type::Num);
+            },
+            StatementKind::Spin(label, command, target) => {
+                self.read(label, Type::Str, name);
+                self.read(command, Type::Any, name);
+                if let Some(target) = target {
+                    self.assign(target, Type::Num);
+                }
+            },
+            StatementKind::Gauge(label, source) => {
+                self.read(label, Type::Str, name);
+                match source {
+                    GaugeSource::Value(value) => self.read(value, Type::Num, name),
+                    GaugeSource::Exec(command, target) => {
+                        self.read(command, Type::Any, name);
+                        if let Some(target) = target {
+                            self.assign(target, Type::Num);
+                        }
+                    },
+                }
+            },
+            StatementKind::Tail(target, source) => {
+                self.input();
+                match source {
+                    TailSource::Exec(command) => self.read(command, Type::Any, name),
+                    TailSource::File(path) => self.read(path, Type::Str, name),
+                }
+                self.assign(target, Type::List);
+            },
+            StatementKind::Pager(text, _) => {
+                self.read(text, Type::Str, name);
+                self.input();
+            },
+            StatementKind::Form(fields, button) => {
+                let positions = fields.iter().map(|field| &field.at).chain(button.iter().map(|button| &button.at));
+                for (line, column) in positions.flatten() {
+                    self.read(line, Type::Num, name);
+                    self.read(column, Type::Num, name);
+                }
+                for field in fields {
+                    self.read(&field.label, Type::Str, name);
+                    if let FieldKind::Choice(options) = &field.kind {
+                        self.read(options, Type::List, name);
+                    }
+                }
+                if let Some(button) = button {
+                    self.read(&button.label, Type::Str, name);
+                }
+                self.input();
+                for field in fields {
+                    self.assign(&field.target, if field.kind == FieldKind::Number { Type::Num } else { Type::Str });
+                }
+            },
+            StatementKind::Set(target, value) => {
+                self.read(value, Type::Any, name);
+                if let Operand::Identifier(variable, span) = target {
+                    let base = &variable[..variable.find('.').unwrap_or(variable.len())];
+                    if !self.types.contains_key(base) {
+                        self.diagnostics.push(Diagnostic::error(*span, format!("`{}` is not declared; use LET", base)));
+                        return;
+                    }
+                }
+                let found = Type::of(value, &self.types);
+                self.assign(target, found);
+            },
+            // Files are only read when includes are expanded, which happens before checking
+            StatementKind::Include(_) => {},
+            StatementKind::Return(value) => {
+                if self.depth == 0 {
+                    self.diagnostics.push(Diagnostic::error(statement.span, "RETURN provided outside of a procedure".to_owned()));
+                }
+                if let Some(value) = value {
+                    self.read(value, Type::Any, name);
+                }
+            },
+        }
+    }
+
+    // Handlers only run while input is read, so this is where a pending one is checked, against
+    // the variables that exist at that point. Whatever the handler assigns may never happen,
+    // so none of it is kept.
+    fn input(&mut self) {
+        if let Some((_, body)) = self.pending.take() {
+            let types = self.types.clone();
+            let positions = self.positions.clone();
+            self.statements(&body);
+            self.types = types;
+            self.positions = positions;
+        }
+    }
+
+    fn unreachable(&mut self) {
+        if let Some((span, _)) = self.pending.take() {
+            self.diagnostics.push(Diagnostic::warning(span, "Unreachable handler: no input is read while it is registered, so it never runs".to_owned()));
+        }
+    }
+
+    fn read(&mut self, operand: &Operand, expected: Type, operator: &str) {
+        match operand {
+            Operand::Identifier(name, span) if name.contains('.') => self.field(name, *span),
+            Operand::Identifier(name, span) => {
+                match self.types.get(name) {
+                    // Arguments such as `$1` are only known once the program runs
+                    None if built_in(name).is_some() => {},
+                    None => {
+                        self.diagnostics.push(Diagnostic::error(*span, format!("`{}` is read before it is ever assigned", name)));
+                    },
+                    Some(found) if *found != expected && *found != Type::Any && expected != Type::Any => {
+                        self.diagnostics.push(Diagnostic::error(*span, format!("Type mismatch: {} expects a {} but `{}` holds a {}", operator, expected.name(), name, found.name())));
+                    },
+                    _ => {},
+                }
+            },
+            Operand::Literal(value, quoted, span) => {
+                if (expected == Type::Num && (*quoted || value.parse::<i32>().is_err())) || expected == Type::List || expected == Type::Map || expected == Type::Bool {
+                    self.diagnostics.push(Diagnostic::error(*span, format!("Type mismatch: {} expects a {} but `{}` is not one", operator, expected.name(), value)));
+                }
+            },
+            Operand::Call(function, span) => self.function(function, 0, *span),
+            Operand::List(items, span) => {
+                for item in items.iter() {
+                    self.read(item, Type::Any, operator);
+                }
+                if expected != Type::List && expected != Type::Any {
+                    self.diagnostics.push(Diagnostic::error(*span, format!("Type mismatch: {} expects a {} but `{}` is a list", operator, expected.name(), operand)));
+                }
+            },
+            Operand::Map(fields, span) => {
+                for (_, value) in fields.iter() {
+                    self.read(value, Type::Any, operator);
+                }
+                if expected != Type::Map && expected != Type::Any {
+                    self.diagnostics.push(Diagnostic::error(*span, format!("Type mismatch: {} expects a {} but `{}` is a map", operator, expected.name(), operand)));
+                }
+            },
+            Operand::Bool(..) | Operand::Compare(..) => {
+                if let Operand::Compare(left, _, right, _) = operand {
+                    self.read(left, Type::Any, operator);
+                    self.read(right, Type::Any, operator);
+                }
+                if expected != Type::Bool && expected != Type::Any {
+                    self.diagnostics.push(Diagnostic::error(operand.span(), format!("Type mismatch: {} expects a {} but `{}` is a bool", operator, expected.name(), operand)));
+                }
+            },
+            // Items may be of any type
+            Operand::Index(list, index, _) => {
+                self.read(list, Type::List, operator);
+                self.read(index, Type::Num, operator);
+            },
+        }
+    }
+
+    fn function(&mut self, function: &str, arguments: usize, span: Span) {
+        match self.procedures.get(function) {
+            Some(parameters) if *parameters != arguments => {
+                self.diagnostics.push(Diagnostic::error(span, format!("`{}` takes {} arguments but was given {}", function, parameters, arguments)));
+            },
+            Some(_) => {},
+            None if !self.functions.contains(function) => {
+                self.diagnostics.push(Diagnostic::error(span, format!("Unknown function `{}`", function)));
+            },
+            None => {},
+        }
+    }
+
+    // Fields, as in `cfg.port`, may hold anything, but what they are read from has to be a map
+    fn field(&mut self, name: &str, span: Span) {
+        let base = &name[..name.find('.').unwrap_or(name.len())];
+        match self.types.get(base) {
+            None => {
+                self.diagnostics.push(Diagnostic::error(span, format!("`{}` is read before it is ever assigned", base)));
+            },
+            Some(Type::Map) | Some(Type::Any) => {},
+            Some(found) => {
+                self.diagnostics.push(Diagnostic::error(span, format!("`{}` holds a {}, which has no fields", base, found.name())));
+            },
+        }
+    }
+
+    // STRING and NUM change a variable but cannot create one
+    fn declared(&mut self, target: &Operand, operator: &str) {
+        if let Operand::Identifier(name, span) = target {
+            // Fields are checked when they are assigned
+            if !name.contains('.') && !self.types.contains_key(name) {
+                self.diagnostics.push(Diagnostic::error(*span, format!("{} assigns to `{}`, which was never declared", operator, name)));
+            }
+        }
+    }
+
+    // A variable keeps the type it was first given, except for `out`
+    fn assign(&mut self, target: &Operand, found: Type) {
+        if let Operand::Identifier(name, span) = target {
+            if !self.writable(target) {
+                return;
+            }
+            if name.contains('.') {
+                self.field(name, *span);
+                return;
+            }
+            match self.types.get(name) {
+                Some(old) if *old != found && *old != Type::Any && found != Type::Any && name != "out" => {
+                    self.diagnostics.push(Diagnostic::error(*span, format!("`{}` holds a {}, which cannot be replaced by a {}", name, old.name(), found.name())));
+                },
+                _ => {
+                    self.types.insert(name.to_owned(), found);
+                },
+            }
+        }
+    }
+
+    // The item of a FOR loop takes whatever type it is given
+    fn redeclare(&mut self, target: &Operand, found: Type) {
+        if let Operand::Identifier(name, _) = target {
+            if self.writable(target) {
+                self.types.insert(name.to_owned(), found);
+            }
+        }
+    }
+
+    // Constants and built-in variables cannot be assigned by scripts
+    fn writable(&mut self, target: &Operand) -> bool {
+        if let Operand::Identifier(name, span) = target {
+            let base = &name[..name.find('.').unwrap_or(name.len())];
+            if let Some(changed) = built_in(base) {
+                self.diagnostics.push(Diagnostic::error(*span, format!("`{}` is built in and {}", base, changed)));
+                return false;
+            }
+            if self.constants.contains(base) {
+                self.diagnostics.push(Diagnostic::error(*span, format!("`{}` is a constant", base)));
+                return false;
+            }
+        }
+        true
+    }
+
+    fn component(&mut self, operand: &Operand) {
+        if let Operand::Literal(value, _, span) = operand {
+            match value.parse::<i16>() {
+                Ok(component) if (0..=1000).contains(&component) => {},
+                _ => {
+                    self.diagnostics.push(Diagnostic::error(*span, format!("Color component `{}` is not a number from 0 to 1000", value)));
+                },
+            }
+        }
+    }
+}
+
+// What changes the built-in variables, which scripts cannot assign themselves. `out` is the
+// exception, since programs leave their output in it.
+pub fn built_in(name: &str) -> Option<&'static str> {
+    match name {
+        "x" | "y" => Some("can only be changed by MOVE and SHIFT"),
+        "G" | "$" => Some("only changes when the terminal is resized"),
+        "foreground" | "background" => Some("cannot be changed"),
+        "args" => Some("comes from the command line"),
+        _ if name.len() > 1 && name.starts_with('$') && name[1..].bytes().all(|b| b.is_ascii_digit()) => Some("comes from the command line"),
+        _ => None,
+    }
+}
+
+// Whether a statement always ends the procedure it is in: a RETURN, or an IF that returns
+// whichever way it goes
+fn returns(statement: &Statement) -> bool {
+    match &statement.kind {
+        StatementKind::Return(_) => true,
+        StatementKind::If(_, body, otherwise) => body.iter().any(returns) && otherwise.iter().any(returns),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+fn messages(raw: &str) -> Vec<String> {
+    let program = crate::parser::parse_tokens(&crate::lexer::derive_tokens(raw).unwrap()).unwrap();
+    check(&program).iter().map(|diagnostic| diagnostic.to_string()).collect()
+}
+
+#[test]
+fn check_test() {
+    assert!(messages("CLR c 0 0 0; FORE c; AWAIT name; PRINT name; MOVE G $").is_empty());
+    assert_eq!(messages("PRINT missing; LET n = 1;\nAWAIT a; FORE a; NUM n a; STRING s \"t\"; CLR c 2000 0 0"), vec![
+        "1:7: error: `missing` is read before it is ever assigned",
+        "2:15: error: Type mismatch: FORE expects a color but `a` holds a string",
+        "2:24: error: Type mismatch: NUM expects a number but `a` holds a string",
+        "2:34: error: STRING assigns to `s`, which was never declared",
+        "2:47: error: Color component `2000` is not a number from 0 to 1000",
+    ]);
+    assert_eq!(messages("RESTOREPOS top; SNAPSHOT \"a\" FORMAT pdf"), vec![
+        "1:12: error: Position `top` is restored before it is ever saved",
+        "1:37: error: Unknown format `pdf` provided to SNAPSHOT operator",
+    ]);
+}
+
+#[test]
+fn check_handler_test() {
+    // Handlers see the variables assigned before the input that lets them run
+    assert!(messages("ON RESIZE; FORE c; END; CLR c 0 0 0; AWAIT out").is_empty());
+    // A resize during the AWAIT that assigns `name` would run the handler before it exists
+    assert_eq!(messages("ON RESIZE; PRINT name; END; AWAIT name"), vec![
+        "1:18: error: `name` is read before it is ever assigned",
+    ]);
+    assert_eq!(messages("ON RESIZE; PRINT \"a\"; END; ON RESIZE; PRINT \"b\"; END; AWAIT out; ON RESIZE; END"), vec![
+        "1:1: warning: Unreachable handler: no input is read while it is registered, so it never runs",
+        "1:66: warning: Unreachable handler: no input is read while it is registered, so it never runs",
+    ]);
+}
+
+#[test]
+fn check_function_test() {
+    let program = Program::parse("CALL branches \"origin\" INTO list; PRINT list; MOVE list 0; PRINT version(); CALL tags").unwrap();
+    let messages: Vec<String> = check_with(&program, &["branches".to_owned(), "version".to_owned()]).iter().map(|d| d.to_string()).collect();
+    assert_eq!(messages, vec!["1:82: error: Unknown function `tags`"]);
+    assert_eq!(check(&program).len(), 2);
+}
+
+#[test]
+fn check_procedure_test() {
+    assert!(messages("PROC header title; PRINT title; CLR c 0 0 0; RETURN c; END; CALL header \"a\" INTO c; FORE c").is_empty());
+    assert_eq!(messages("PROC header title; CLR c 0 0 0; END; CALL header; FORE c; RETURN; CALL footer 1"), vec![
+        "1:43: error: `header` takes 1 arguments but was given 0",
+        "1:56: error: `c` is read before it is ever assigned",
+        "1:59: error: RETURN provided outside of a procedure",
+        "1:67: warning: Unreachable code: the procedure has always returned before this point",
+        "1:72: error: Unknown function `footer`",
+    ]);
+    // After a RETURN, or an IF that returns either way, nothing runs
+    assert_eq!(messages("PROC twice n; RETURN n; PRINT \"never\"; PRINT \"again\"; END;
+PROC pick a; IF a; RETURN 1; ELSE; RETURN 2; END; PRINT \"gone\"; END;
+PROC half a; IF a; RETURN 1; END; PRINT \"kept\"; END"), vec![
+        "1:25: warning: Unreachable code: the procedure has always returned before this point",
+        "2:51: warning: Unreachable code: the procedure has always returned before this point",
+    ]);
+}
+
+#[test]
+fn check_list_test() {
+    assert!(messages("LIST xs [\"a\", x]; PUSH xs 1; FOR item IN xs; PRINT item; END; MOVE xs[0] 0; POP xs last").is_empty());
+    assert_eq!(messages("LIST xs \"a\"; PRINT [\"a\"]; AWAIT s; FOR c IN s; END; MOVE s[0] 0"), vec![
+        "1:9: error: Type mismatch: LIST expects a list but `a` is not one",
+        "1:20: error: Type mismatch: PRINT expects a string but `[\"a\"]` is a list",
+        "1:45: error: Type mismatch: FOR expects a list but `s` holds a string",
+        "1:58: error: Type mismatch: MOVE expects a list but `s` holds a string",
+    ]);
+}
+
+#[test]
+fn check_map_test() {
+    assert!(messages("MAP cfg { port: 8080 }; NUM cfg.port 1; MOVE cfg.port 0; AWAIT cfg.name; FOR key IN cfg; PRINT key; END").is_empty());
+    assert_eq!(messages("MAP cfg [1]; PRINT form.name; AWAIT G.y; MOVE { a: 1 } 0"), vec![
+        "1:9: error: Type mismatch: MAP expects a map but `[1]` is a list",
+        "1:20: error: `form` is read before it is ever assigned",
+        "1:37: error: `G` is built in and only changes when the terminal is resized",
+        "1:47: error: Type mismatch: MOVE expects a number but `{a: 1}` is a map",
+    ]);
+}
+
+#[test]
+fn check_bool_test() {
+    assert!(messages("CONFIRM sure; BOOL big x > 3; IF sure; PRINT \"a\"; ELSE; IF big == FALSE; END; END").is_empty());
+    assert_eq!(messages("BOOL b TRUE; PRINT b; MOVE x == 1 0; IF missing; END"), vec![
+        "1:20: error: Type mismatch: PRINT expects a string but `b` holds a bool",
+        "1:28: error: Type mismatch: MOVE expects a number but `x == 1` is a bool",
+        "1:41: error: `missing` is read before it is ever assigned",
+    ]);
+}
+
+#[test]
+fn check_declaration_test() {
+    assert!(messages("CONST width = 40; LET count = 0; SET count = width; AWAIT out; LIST out [count]").is_empty());
+    assert_eq!(messages("LET count = 0; LET count = 1; SET count = \"a\"; SET size = 1; CONST w = 1; NUM w 2; AWAIT y"), vec![
+        "1:20: error: `count` is already declared",
+        "1:35: error: `count` holds a number, which cannot be replaced by a string",
+        "1:52: error: `size` is not declared; use LET",
+        "1:79: error: `w` is a constant",
+        "1:90: error: `y` is built in and can only be changed by MOVE and SHIFT",
+    ]);
+    // Quotes decide the declared type, even of digits
+    assert_eq!(messages("LET zip = \"02134\"; STRING out zip; LET n = 0; NUM n zip"), vec![
+        "1:53: error: Type mismatch: NUM expects a number but `zip` holds a string",
+    ]);
+}
+
+#[test]
+fn check_form_test() {
+    assert!(messages("LET editors = [\"vi\"]; FORM TEXT name \"Name\"; CHOICE editor \"Editor\" editors AT 3 0 END; PRINT name; PRINT editor").is_empty());
+    assert_eq!(messages("LET age = \"\"; FORM NUMBER age \"Age\"; CHOICE c \"C\" 1 END"), vec![
+        "1:27: error: `age` holds a string, which cannot be replaced by a number",
+        "1:51: error: Type mismatch: FORM expects a list but `1` is not one",
+    ]);
+}
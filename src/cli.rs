@@ -0,0 +1,267 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use pzx::backend::Key;
+use pzx::check;
+use pzx::debug::Breakpoint;
+use pzx::keys::parse_keys;
+use pzx::trace::LogLevel;
+
+pub const USAGE: &str = "Usage: pzx [options] <instructions | script file> [arguments]
+       pzx --repl [options] [instructions | script file] [arguments]
+       pzx test [--bless] [--size <lines>x<cols>] <directory>
+
+Options:
+    --check                Report problems in the instructions without running them
+    --dump-tokens          Print the tokens the instructions are split into, without running them
+    --dump-ast             Print the statements the instructions are parsed into, without running them
+    --json                 With --dump-tokens or --dump-ast, print JSON instead
+    --keys <keys>          Replay keys such as \"y<Enter>John<Tab>\" instead of reading the keyboard
+    --keys-file <path>     Replay the keys written in a file
+    --key-delay <ms>       Wait between replayed keys, for demos
+    --headless             Run against an in-memory screen instead of the terminal
+    --size <lines>x<cols>  Size of the in-memory screen (default 24x80)
+    --snapshot-on-exit <path>
+                           Save the final screen as text, or as ANSI or HTML for .ansi or .html paths
+    --trace <path>         Write every executed statement and the variables it changed to a file
+    --log-level <level>    With --trace, info (statements) or debug (also colors and keys)
+    --repl                 Run statements as they are typed at a prompt below the screen
+    --debug                Run under a step debugger shown below the program
+    --break <line | OPERATOR>
+                           With --debug, pause before that line or every statement of that operator
+    -D, --var <name>=<value>
+                           Set a variable before the program runs; LET and CONST keep its value
+    --                     Take everything that follows as arguments, even if it starts with --
+    --bless                With test, overwrite the expected results with the actual ones";
+
+pub enum Mode {
+    Run,
+    Check,
+    Repl,
+    DumpTokens,
+    DumpAst,
+    Test(PathBuf),
+}
+
+pub struct Options {
+    pub mode: Mode,
+    pub commands: String,
+    pub script: Option<PathBuf>, // Where the instructions were read from, if they came from a file
+    pub keys: Option<Vec<Key>>,
+    pub key_delay: Duration,
+    pub headless: bool,
+    pub size: (i32, i32),
+    pub snapshot_on_exit: Option<String>,
+    pub bless: bool,
+    pub json: bool,
+    pub trace: Option<String>,
+    pub log_level: LogLevel,
+    pub debug: bool,
+    pub breakpoints: Vec<Breakpoint>,
+    pub vars: Vec<(String, String)>, // From -D and --var, in the order given
+    pub args: Vec<String>, // Whatever follows the instructions, which scripts read as `args`
+}
+
+pub fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut options = Options {
+        mode: Mode::Run,
+        commands: String::new(),
+        script: None,
+        keys: None,
+        key_delay: Duration::from_millis(0),
+        headless: false,
+        size: (24, 80),
+        snapshot_on_exit: None,
+        bless: false,
+        json: false,
+        trace: None,
+        log_level: LogLevel::Info,
+        debug: false,
+        breakpoints: Vec::new(),
+        vars: Vec::new(),
+        args: Vec::new(),
+    };
+    let mut log_level = None;
+    let mut positional: Vec<String> = Vec::new();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--keys" => {
+                let raw = value(&mut args, &arg)?;
+                append_keys(&mut options, parse_keys(&raw)?);
+            },
+            "--keys-file" => {
+                let path = value(&mut args, &arg)?;
+                let raw = fs::read_to_string(&path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+                append_keys(&mut options, parse_keys(&raw)?);
+            },
+            "--key-delay" => {
+                let raw = value(&mut args, &arg)?;
+                let ms = raw.parse::<u64>().map_err(|_| format!("Improper delay provided to --key-delay: {}", raw))?;
+                options.key_delay = Duration::from_millis(ms);
+            },
+            "--headless" => {
+                options.headless = true;
+            },
+            "--size" => {
+                let raw = value(&mut args, &arg)?;
+                options.size = parse_size(&raw).ok_or(format!("Improper size provided to --size: {}", raw))?;
+            },
+            "--snapshot-on-exit" => {
+                options.snapshot_on_exit = Some(value(&mut args, &arg)?);
+            },
+            "--check" => {
+                options.mode = Mode::Check;
+            },
+            "--dump-tokens" => {
+                options.mode = Mode::DumpTokens;
+            },
+            "--dump-ast" => {
+                options.mode = Mode::DumpAst;
+            },
+            "--json" => {
+                options.json = true;
+            },
+            "--trace" => {
+                options.trace = Some(value(&mut args, &arg)?);
+            },
+            "--log-level" => {
+                let raw = value(&mut args, &arg)?;
+                log_level = Some(LogLevel::from_name(&raw).ok_or(format!("Improper level provided to --log-level: {}", raw))?);
+            },
+            "--repl" => {
+                options.mode = Mode::Repl;
+            },
+            "--debug" => {
+                options.debug = true;
+            },
+            "--break" => {
+                let raw = value(&mut args, &arg)?;
+                options.breakpoints.push(Breakpoint::parse(&raw).ok_or(format!("Improper breakpoint provided to --break: {}", raw))?);
+            },
+            "--bless" => {
+                options.bless = true;
+            },
+            "-D" | "--var" => {
+                let raw = value(&mut args, &arg)?;
+                options.vars.push(parse_var(&raw).ok_or(format!("Improper variable provided to {}: {}", arg, raw))?);
+            },
+            _ if arg.starts_with("-D") => {
+                options.vars.push(parse_var(&arg[2..]).ok_or(format!("Improper variable provided to -D: {}", &arg[2..]))?);
+            },
+            "--" => {
+                positional.extend(&mut args);
+            },
+            _ if arg.starts_with("--") => {
+                return Err(format!("Unknown option {}", arg));
+            },
+            _ => {
+                positional.push(arg);
+            },
+        }
+    }
+
+    if let Some(level) = log_level {
+        if options.trace.is_none() {
+            return Err("--log-level provided without --trace".to_owned());
+        }
+        options.log_level = level;
+    }
+
+    if options.debug && options.headless {
+        return Err("--debug needs a terminal and cannot be combined with --headless".to_owned());
+    }
+    if let Mode::Repl = options.mode {
+        if options.headless || options.debug {
+            return Err("--repl needs a terminal of its own and cannot be combined with --headless or --debug".to_owned());
+        }
+    }
+    if !options.breakpoints.is_empty() && !options.debug {
+        return Err("--break provided without --debug".to_owned());
+    }
+
+    if positional.first().map(|arg| arg.as_str()) == Some("test") {
+        if positional.len() > 2 {
+            return Err("More than one test directory provided".to_owned());
+        }
+        let dir = positional.get(1).map(|dir| dir.as_str()).unwrap_or(".");
+        options.mode = Mode::Test(PathBuf::from(dir));
+        return Ok(options);
+    }
+
+    // The REPL can start from nothing
+    let mut positional = positional.into_iter();
+    let commands = match (positional.next(), &options.mode) {
+        (Some(commands), _) => commands,
+        (None, Mode::Repl) => String::new(),
+        (None, _) => return Err("No instructions provided".to_owned()),
+    };
+
+    // Instructions that name a file are read from it
+    let path = Path::new(&commands);
+    if path.is_file() {
+        options.commands = fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", commands, e))?;
+        options.script = Some(path.to_path_buf());
+    } else {
+        options.commands = commands;
+    }
+    options.args = positional.collect();
+
+    Ok(options)
+}
+
+fn value<I: Iterator<Item = String>>(args: &mut I, option: &str) -> Result<String, String> {
+    args.next().ok_or(format!("No value provided to {}", option))
+}
+
+// --keys and --keys-file may both be given; their keys are replayed in order
+fn append_keys(options: &mut Options, keys: Vec<Key>) {
+    options.keys.get_or_insert_with(Vec::new).extend(keys);
+}
+
+fn parse_size(raw: &str) -> Option<(i32, i32)> {
+    let mut parts = raw.split('x');
+    let lines = parts.next()?.parse().ok()?;
+    let cols = parts.next()?.parse().ok()?;
+    if parts.next().is_some() || lines < 1 || cols < 1 {
+        return None;
+    }
+    Some((lines, cols))
+}
+
+// `name=value`. The value is kept as text until the script declares what type it has. Built-in
+// variables cannot be set this way.
+fn parse_var(raw: &str) -> Option<(String, String)> {
+    let (name, value) = raw.split_at(raw.find('=')?);
+    let value = &value[1..];
+    if name.is_empty() || name.contains(char::is_whitespace) || check::built_in(name).is_some() {
+        return None;
+    }
+    Some((name.to_owned(), value.to_owned()))
+}
+
+#[test]
+fn parse_args_test() {
+    let args = vec!["--headless", "--keys", "a<Enter>", "--size", "10x20", "AWAIT out"];
+    let options = parse_args(args.into_iter().map(|arg| arg.to_owned())).unwrap();
+    assert_eq!(options.commands, "AWAIT out");
+    assert_eq!(options.keys, Some(vec![Key::Char('a'), Key::Enter]));
+    assert_eq!(options.size, (10, 20));
+    assert!(options.headless);
+
+    let options = parse_args(vec!["test".to_owned(), "--bless".to_owned(), "tests".to_owned()].into_iter()).unwrap();
+    assert!(matches!(options.mode, Mode::Test(ref dir) if dir == Path::new("tests")));
+    assert!(options.bless);
+
+    assert!(parse_args(vec!["--keys".to_owned()].into_iter()).is_err());
+    assert!(parse_args(Vec::new().into_iter()).is_err());
+
+    let args = vec!["-Dtitle=Setup", "--var", "port=80", "PRINT title", "a", "--", "--b"];
+    let options = parse_args(args.into_iter().map(|arg| arg.to_owned())).unwrap();
+    assert_eq!(options.vars, vec![("title".to_owned(), "Setup".to_owned()), ("port".to_owned(), "80".to_owned())]);
+    assert_eq!(options.args, vec!["a", "--b"]);
+    // Built-in variables cannot be set, and values need a name
+    assert!(parse_args(vec!["-D".to_owned(), "x=1".to_owned(), "PRINT x".to_owned()].into_iter()).is_err());
+    assert!(parse_args(vec!["--var".to_owned(), "title".to_owned(), "PRINT x".to_owned()].into_iter()).is_err());
+}
@@ -0,0 +1,339 @@
+use crate::interpreter::{self, Interpreter, Variable};
+use crate::backend::{Attribute, Backend, Key};
+use crate::lexer::{derive_tokens, Operator, Token};
+use crate::parser::Statement;
+use crate::trace;
+
+// Lines of the terminal given to the debugger: a status line, the source line, the output of
+// commands and the prompt
+pub const PANE_LINES: i32 = 8;
+
+const HELP: &str = "step (s, or Enter), continue (c), break (b) <line | OPERATOR>, delete (d) <line | OPERATOR>, print (p) <name>, vars, set <name> <value>, stop (q)";
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Breakpoint {
+    Line(usize),
+    Operator(Operator),
+}
+
+impl Breakpoint {
+    // A line number, or an operator such as MOVE in any case
+    pub fn parse(raw: &str) -> Option<Breakpoint> {
+        if let Ok(line) = raw.parse::<usize>() {
+            return if line > 0 { Some(Breakpoint::Line(line)) } else { None };
+        }
+        match derive_tokens(&raw.to_uppercase()).ok()?.as_slice() {
+            [(Token::Operator(operator), _)] => Some(Breakpoint::Operator(*operator)),
+            _ => None,
+        }
+    }
+}
+
+// What the program does once the debugger hands control back
+enum Resume {
+    Step,
+    Continue,
+    Stop,
+}
+
+pub struct Debugger {
+    pub pane: Box<dyn Backend>,
+    source: Vec<String>,
+    breakpoints: Vec<Breakpoint>,
+    stepping: bool,
+    line: usize, // The line of the statement that ran last, so a line breakpoint only pauses once
+    output: Vec<String>,
+}
+
+impl Debugger {
+    // Programs start paused before their first statement
+    pub fn new(pane: Box<dyn Backend>, source: &str, breakpoints: Vec<Breakpoint>) -> Debugger {
+        Debugger {
+            pane,
+            source: source.lines().map(|line| line.to_owned()).collect(),
+            breakpoints,
+            stepping: true,
+            line: 0,
+            output: vec!["Commands: ".to_owned() + HELP],
+        }
+    }
+
+    // Runs before every statement, taking commands whenever the program should pause.
+    // Returns false when the program is stopped.
+    pub fn pause(&mut self, statement: &Statement, state: &mut Interpreter) -> bool {
+        let line = statement.span.line;
+        let hit = self.breakpoints.iter().any(|breakpoint| match breakpoint {
+            // Line numbers are those of the script, not of the files it includes
+            Breakpoint::Line(l) => *l == line && self.line != line && statement.span.file == 0,
+            Breakpoint::Operator(operator) => *operator == statement.operator,
+        });
+        self.line = line;
+        if !self.stepping && !hit {
+            return true;
+        }
+
+        loop {
+            self.render(statement, state);
+            let resume = match self.read_line() {
+                Some(command) => self.command(&command, state),
+                // Without a keyboard nothing more can be asked, so the program runs to the end
+                None => {
+                    self.breakpoints.clear();
+                    Some(Resume::Continue)
+                },
+            };
+            match resume {
+                Some(Resume::Step) => {
+                    self.stepping = true;
+                    return true;
+                },
+                Some(Resume::Continue) => {
+                    self.stepping = false;
+                    return true;
+                },
+                Some(Resume::Stop) => return false,
+                None => {},
+            }
+        }
+    }
+
+    fn command(&mut self, command: &str, state: &mut Interpreter) -> Option<Resume> {
+        let mut words = command.split_whitespace();
+        match words.next() {
+            None | Some("s") | Some("step") => return Some(Resume::Step),
+            Some("c") | Some("continue") => return Some(Resume::Continue),
+            Some("q") | Some("stop") => return Some(Resume::Stop),
+            Some("b") | Some("break") => match words.next().and_then(Breakpoint::parse) {
+                Some(breakpoint) => {
+                    self.breakpoints.push(breakpoint);
+                    self.output.push(format!("Breakpoint set at {}", describe(breakpoint)));
+                },
+                None => self.output.push("Usage: break <line | OPERATOR>".to_owned()),
+            },
+            Some("d") | Some("delete") => match words.next().and_then(Breakpoint::parse) {
+                Some(breakpoint) => {
+                    self.breakpoints.retain(|b| *b != breakpoint);
+                    self.output.push(format!("Breakpoint at {} deleted", describe(breakpoint)));
+                },
+                None => self.output.push("Usage: delete <line | OPERATOR>".to_owned()),
+            },
+            Some("p") | Some("print") => match words.next() {
+                Some(name) => match state.lookup(name) {
+                    Some(value) => self.output.push(format!("{}={}", name, value)),
+                    None => self.output.push(format!("`{}` is not defined", name)),
+                },
+                None => self.output.push("Usage: print <name>".to_owned()),
+            },
+            Some("vars") => {
+                self.output.push(trace::variables(&state.visible()));
+            },
+            Some("set") => {
+                // The value is the rest of the command, since a string may contain spaces
+                let rest = command.trim_start()[3..].trim_start();
+                let (name, raw) = rest.split_at(rest.find(char::is_whitespace).unwrap_or(rest.len()));
+                match parse_value(raw.trim()) {
+                    Some(value) => self.set(name, value, state),
+                    None => self.output.push("Usage: set <name> <number | \"string\" | TRUE | FALSE | r g b>".to_owned()),
+                }
+            },
+            Some("h") | Some("help") => self.output.push(HELP.to_owned()),
+            Some(other) => self.output.push(format!("Unknown command `{}`; try help", other)),
+        }
+        None
+    }
+
+    // Everything else is changed as SET would change it, but the built-in variables that only
+    // the terminal changes can be changed here too, keeping their type. x and y move the cursor
+    // since they always hold its position, and the colors are applied at once.
+    fn set(&mut self, name: &str, value: Variable, state: &mut Interpreter) {
+        if !["x", "y", "G", "$", "foreground", "background"].contains(&name) {
+            match state.set(name, value.clone()) {
+                Ok(()) => self.output.push(format!("{}={}", name, value)),
+                Err(message) => self.output.push(message),
+            }
+            return;
+        }
+
+        if let Some(old) = state.lookup(name) {
+            if std::mem::discriminant(old) != std::mem::discriminant(&value) {
+                self.output.push(format!("`{}` holds {}, which cannot be replaced by {}", name, old, value));
+                return;
+            }
+        }
+        self.output.push(format!("{}={}", name, value));
+        match name {
+            "foreground" => interpreter::change_foreground(state.backend, &value).expect("Err: Could not change foreground color"),
+            "background" => interpreter::change_background(state.backend, &value).expect("Err: Could not change background color"),
+            _ => {},
+        }
+        state.assign(name.to_owned(), value);
+        if name == "x" || name == "y" {
+            if let (Some(Variable::Num(y)), Some(Variable::Num(x))) = (state.variables.get("y"), state.variables.get("x")) {
+                state.backend.move_cursor(*y, *x);
+            }
+        }
+    }
+
+    fn render(&mut self, statement: &Statement, state: &Interpreter) {
+        let (lines, cols) = self.pane.size();
+        let width = (cols - 1).max(0) as usize;
+        self.pane.clear();
+
+        let status = format!(" paused before {}", trace::statement(statement, &state.visible(), &state.visible()));
+        self.pane.move_cursor(0, 0);
+        self.pane.attribute_on(Attribute::Reverse);
+        self.pane.print(&fit(&status, width, true));
+        self.pane.attribute_off(Attribute::Reverse);
+
+        let source = match statement.span.file {
+            0 => self.source.get(statement.span.line - 1).map(|line| line.as_str()).unwrap_or(""),
+            _ => "(in an included file)",
+        };
+        self.pane.move_cursor(1, 0);
+        self.pane.print(&fit(&format!("{:>4} | {}", statement.span.line, source), width, false));
+
+        // Long output is wrapped, and only the newest lines that fit are shown
+        let mut wrapped: Vec<String> = Vec::new();
+        for line in self.output.iter() {
+            let chars: Vec<char> = line.chars().collect();
+            for chunk in chars.chunks(width.max(1)) {
+                wrapped.push(chunk.iter().collect());
+            }
+        }
+        let room = (lines - 3).max(0) as usize;
+        for (i, line) in wrapped.iter().skip(wrapped.len().saturating_sub(room)).enumerate() {
+            self.pane.move_cursor(2 + i as i32, 0);
+            self.pane.print(line);
+        }
+
+        self.pane.move_cursor(lines - 1, 0);
+        self.pane.print("(pzx) ");
+        self.pane.refresh();
+    }
+
+    fn read_line(&mut self) -> Option<String> {
+        self.pane.set_cursor_visible(true);
+
+        let mut buffer = String::new();
+        loop {
+            match self.pane.read_key() {
+                Key::Enter => break,
+                Key::Eof => return None,
+                Key::Backspace => {
+                    let (y, x) = self.pane.cursor();
+                    if buffer.pop().is_some() {
+                        self.pane.move_cursor(y, x - 1);
+                        self.pane.delete_char();
+                    }
+                },
+                Key::Char(c) => {
+                    buffer.push(c);
+                    self.pane.print(&c.to_string());
+                },
+                _ => {},
+            }
+        }
+
+        self.pane.set_cursor_visible(false);
+        Some(buffer)
+    }
+}
+
+fn describe(breakpoint: Breakpoint) -> String {
+    match breakpoint {
+        Breakpoint::Line(line) => format!("line {}", line),
+        Breakpoint::Operator(operator) => operator.name().to_owned(),
+    }
+}
+
+// Cuts text to the width of the pane, or pads it to fill the line
+fn fit(text: &str, width: usize, pad: bool) -> String {
+    let res: String = text.chars().take(width).collect();
+    if pad {
+        format!("{:<1$}", res, width)
+    } else {
+        res
+    }
+}
+
+// A number, a quoted string, or the three components of a color
+fn parse_value(raw: &str) -> Option<Variable> {
+    if raw.len() >= 2 && raw.starts_with('"') && raw.ends_with('"') {
+        return Some(Variable::Str(raw[1..raw.len() - 1].to_owned()));
+    }
+    if let Ok(i) = raw.parse::<i32>() {
+        return Some(Variable::Num(i));
+    }
+    match raw {
+        "TRUE" => return Some(Variable::Bool(true)),
+        "FALSE" => return Some(Variable::Bool(false)),
+        _ => {},
+    }
+
+    let components: Vec<i16> = raw.split_whitespace().filter_map(|c| c.parse().ok()).filter(|c| (0..=1000).contains(c)).collect();
+    match (components.as_slice(), raw.split_whitespace().count()) {
+        ([r, g, b], 3) => Some(Variable::Clr { r: *r, g: *g, b: *b }),
+        _ => None,
+    }
+}
+
+#[test]
+fn debugger_test() {
+    use crate::backend::VirtualScreen;
+    use crate::parser::Program;
+
+    let source = "MOVE 2 2;\nLET n = 1;\nPRINT \"hi\"";
+    let mut pane = VirtualScreen::new(PANE_LINES, 60);
+    // Paused before MOVE: change out, break at PRINT and run to it, then look at y
+    pane.push_str("set out \"changed\"\nb print\nc\np y\nset y \"no\"\nc\n");
+
+    let mut screen = VirtualScreen::new(10, 40);
+    let mut state = Interpreter::new(&mut screen);
+    state.set_debugger(Debugger::new(Box::new(pane), source, Vec::new()));
+    let program = Program::parse(source).unwrap();
+    assert_eq!(state.run(&program).unwrap().out, "changed");
+
+    let pane = state.debugger.take().unwrap().pane.snapshot();
+    assert_eq!(pane.row(0).trim_end(), " paused before 3:1 PRINT \"hi\"");
+    assert_eq!(pane.row(1), "   3 | PRINT \"hi\"");
+    assert!(pane.text().contains("y=2\n`y` holds 2, which cannot be replaced by \"no\""));
+    drop(state);
+    assert_eq!(screen.row(2), "  hi");
+
+    // Stopping ends the program before the statement it paused at
+    let mut pane = VirtualScreen::new(PANE_LINES, 60);
+    pane.push_str("s\nq\n");
+    let mut screen = VirtualScreen::new(10, 40);
+    let mut state = Interpreter::new(&mut screen);
+    state.set_debugger(Debugger::new(Box::new(pane), source, vec![Breakpoint::parse("3").unwrap()]));
+    assert!(state.run(&Program::parse(source).unwrap()).unwrap().stopped);
+    drop(state);
+    assert_eq!(screen.row(2), "");
+
+    // A field is set inside its map, and constants stay as they are
+    let source = "MAP cfg { port: 80 };\nCONST limit = 3;\nLIST out [cfg.port, limit]";
+    let mut pane = VirtualScreen::new(PANE_LINES, 60);
+    pane.push_str("s\ns\nset cfg.port 8080\nset limit 4\nset missing 1\nc\n");
+    let mut screen = VirtualScreen::new(10, 40);
+    let mut state = Interpreter::new(&mut screen);
+    state.set_debugger(Debugger::new(Box::new(pane), source, Vec::new()));
+    assert_eq!(state.run(&Program::parse(source).unwrap()).unwrap().out, "[8080,3]");
+    assert!(!state.variables.contains_key("cfg.port"));
+    let pane = state.debugger.take().unwrap().pane.snapshot();
+    assert!(pane.text().contains("cfg.port=8080\n`limit` is a constant\n`missing` is not declared; use LET"));
+
+    // Built-in variables keep their type, and the colors show at once
+    let source = "PRINT \"a\"";
+    let mut pane = VirtualScreen::new(PANE_LINES, 60);
+    pane.push_str("set foreground 0 0 1000\nset background 1000 0 0\nset G 5\nset $ \"no\"\nc\n");
+    let mut screen = VirtualScreen::new(10, 40);
+    let mut state = Interpreter::new(&mut screen);
+    state.set_debugger(Debugger::new(Box::new(pane), source, Vec::new()));
+    let outcome = state.run(&Program::parse(source).unwrap()).unwrap();
+    assert_eq!(outcome.variables.get("G"), Some(&Variable::Num(5)));
+    assert_eq!(outcome.variables.get("foreground"), Some(&Variable::Clr { r: 0, g: 0, b: 1000 }));
+    let pane = state.debugger.take().unwrap().pane.snapshot();
+    assert!(pane.text().contains("G=5\n`$` holds 39, which cannot be replaced by \"no\""));
+    drop(state);
+    assert_eq!(screen.cell_colors(0, 0), ((0, 0, 1000), (1000, 0, 0)));
+}
@@ -0,0 +1,61 @@
+use std::fmt;
+
+// Where a token was found in the instructions. Lines and columns count from 1;
+// start and end are byte offsets into the instructions.
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Default)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub start: usize,
+    pub end: usize,
+    pub file: usize, // 0 for the instructions themselves, or which included file, counting from 1
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+// A problem found in a program before it runs
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub span: Span,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn error(span: Span, message: String) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            span,
+            message,
+        }
+    }
+
+    pub fn warning(span: Span, message: String) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Warning,
+            span,
+            message,
+        }
+    }
+}
+
+// Formatted like compiler output, `line:column: error: message`, so editors can jump to it
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{}: {}: {}", self.span, severity, self.message)
+    }
+}
@@ -0,0 +1,144 @@
+use crate::diagnostic::Span;
+use crate::lexer::Token;
+use crate::parser::{Operand, Program, Statement, StatementKind};
+
+// Lists every token with where it was found, one per line, or as a JSON array
+pub fn tokens(tokens: &[(Token, Span)], json: bool) -> String {
+    if json {
+        let items: Vec<String> = tokens.iter().map(|(token, span)| {
+            let (kind, value) = describe_token(token);
+            format!("{{\"kind\":\"{}\",\"value\":{},\"span\":{}}}", kind, quote(&value), span_json(span))
+        }).collect();
+        return format!("[{}]\n", items.join(","));
+    }
+
+    let mut res = String::new();
+    for (token, span) in tokens.iter() {
+        let (kind, value) = describe_token(token);
+        let value = match token {
+            Token::Literal(..) | Token::Identifier(_) => format!("{:?}", value),
+            _ => value,
+        };
+        res.push_str(&format!("{:<8}{:<12}{}\n", span.to_string(), kind, value));
+    }
+    res
+}
+
+// Prints the statements as an indented tree, operands and block bodies below their operator,
+// or as a JSON object
+pub fn program(program: &Program, json: bool) -> String {
+    if json {
+        return format!("{{\"statements\":{}}}\n", statements_json(&program.statements));
+    }
+
+    let mut res = String::new();
+    statements_text(&program.statements, 0, &mut res);
+    res
+}
+
+fn describe_token(token: &Token) -> (&'static str, String) {
+    match token {
+        Token::Operator(operator) => ("operator", operator.name().to_owned()),
+        Token::Separator => ("separator", ";".to_owned()),
+        Token::Identifier(s) => ("identifier", s.to_owned()),
+        Token::Literal(s, _) => ("literal", s.to_owned()),
+        Token::Call(s) => ("call", s.to_owned()),
+        Token::Punctuation(c) => ("punctuation", c.to_string()),
+        Token::Comparison(comparison) => ("comparison", comparison.symbol().to_owned()),
+    }
+}
+
+fn describe_operand(operand: &Operand) -> (&'static str, String) {
+    match operand {
+        Operand::Literal(s, ..) => ("literal", s.to_owned()),
+        Operand::Identifier(s, _) => ("identifier", s.to_owned()),
+        Operand::Call(s, _) => ("call", s.to_owned()),
+        Operand::List(..) => ("list", operand.to_string()),
+        Operand::Index(..) => ("index", operand.to_string()),
+        Operand::Map(..) => ("map", operand.to_string()),
+        Operand::Bool(..) => ("bool", operand.to_string()),
+        Operand::Compare(..) => ("comparison", operand.to_string()),
+    }
+}
+
+fn statements_text(statements: &[Statement], depth: usize, res: &mut String) {
+    let indent = "  ".repeat(depth);
+    for statement in statements.iter() {
+        res.push_str(&format!("{}{} {}\n", indent, statement.span, statement.operator.name()));
+        for operand in statement.kind.operands() {
+            let (kind, value) = describe_operand(operand);
+            res.push_str(&format!("{}  {} {} {:?}\n", indent, operand.span(), kind, value));
+        }
+        if let Some(body) = statement.kind.body() {
+            statements_text(body, depth + 1, res);
+        }
+        if let StatementKind::If(_, _, otherwise) = &statement.kind {
+            if !otherwise.is_empty() {
+                res.push_str(&format!("{}ELSE\n", indent));
+                statements_text(otherwise, depth + 1, res);
+            }
+        }
+    }
+}
+
+fn statements_json(statements: &[Statement]) -> String {
+    let items: Vec<String> = statements.iter().map(|statement| {
+        let operands: Vec<String> = statement.kind.operands().iter().map(|operand| {
+            let (kind, value) = describe_operand(operand);
+            format!("{{\"kind\":\"{}\",\"value\":{},\"span\":{}}}", kind, quote(&value), span_json(&operand.span()))
+        }).collect();
+
+        let mut res = format!("{{\"operator\":\"{}\",\"span\":{},\"operands\":[{}]", statement.operator.name(), span_json(&statement.span), operands.join(","));
+        if let Some(body) = statement.kind.body() {
+            res.push_str(&format!(",\"body\":{}", statements_json(body)));
+        }
+        if let StatementKind::If(_, _, otherwise) = &statement.kind {
+            res.push_str(&format!(",\"else\":{}", statements_json(otherwise)));
+        }
+        res.push('}');
+        res
+    }).collect();
+    format!("[{}]", items.join(","))
+}
+
+fn span_json(span: &Span) -> String {
+    format!("{{\"line\":{},\"column\":{},\"start\":{},\"end\":{}}}", span.line, span.column, span.start, span.end)
+}
+
+pub(crate) fn quote(raw: &str) -> String {
+    let mut res = String::from("\"");
+    for c in raw.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            '\r' => res.push_str("\\r"),
+            '\t' => res.push_str("\\t"),
+            c if (c as u32) < 0x20 => res.push_str(&format!("\\u{:04x}", c as u32)),
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
+
+#[test]
+fn dump_test() {
+    let lexed = crate::lexer::derive_tokens("PRINT \"a\\b\"; ON RESIZE; MOVE 0 x; END").unwrap();
+    let lines: Vec<String> = tokens(&lexed, false).lines().map(|line| line.to_owned()).collect();
+    assert_eq!(lines[..2], ["1:1     operator    PRINT", "1:7     literal     \"a\\\\b\""]);
+    assert!(tokens(&lexed, true).starts_with("[{\"kind\":\"operator\",\"value\":\"PRINT\",\"span\":{\"line\":1,\"column\":1,\"start\":0,\"end\":5}},{\"kind\":\"literal\",\"value\":\"a\\\\b\""));
+
+    let parsed = crate::parser::parse_tokens(&lexed).unwrap();
+    let lines: Vec<String> = program(&parsed, false).lines().map(|line| line.to_owned()).collect();
+    assert_eq!(lines, [
+        "1:1 PRINT",
+        "  1:7 literal \"a\\\\b\"",
+        "1:14 ON",
+        "  1:17 identifier \"RESIZE\"",
+        "  1:25 MOVE",
+        "    1:30 literal \"0\"",
+        "    1:32 identifier \"x\"",
+    ]);
+    assert!(program(&parsed, true).contains("\"operator\":\"ON\",\"span\":{\"line\":1,\"column\":14,\"start\":13,\"end\":15},\"operands\":[{\"kind\":\"identifier\",\"value\":\"RESIZE\""));
+}
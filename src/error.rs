@@ -0,0 +1,34 @@
+use std::fmt;
+
+use crate::diagnostic::{Diagnostic, Span};
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum PzxError {
+    // The instructions could not be read; every problem found is listed
+    Parse(Vec<Diagnostic>),
+    // A statement could not be carried out
+    Runtime(Diagnostic),
+}
+
+impl PzxError {
+    pub fn runtime(span: Span, message: String) -> PzxError {
+        PzxError::Runtime(Diagnostic::error(span, message))
+    }
+
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            PzxError::Parse(diagnostics) => diagnostics.clone(),
+            PzxError::Runtime(diagnostic) => vec![diagnostic.clone()],
+        }
+    }
+}
+
+// One diagnostic per line, the same way --check reports them
+impl fmt::Display for PzxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let lines: Vec<String> = self.diagnostics().iter().map(|diagnostic| diagnostic.to_string()).collect();
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+impl std::error::Error for PzxError {}
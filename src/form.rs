@@ -0,0 +1,282 @@
+use crate::backend::{Attribute, Backend, Key};
+
+// How many columns an input takes at most, when the screen leaves room for it
+pub const INPUT_WIDTH: usize = 30;
+
+// What a field of a FORM takes
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum Input {
+    Text,
+    Number,
+    Password, // Shown as `*`
+    Choice(Vec<String>),
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct Entry {
+    pub label: String,
+    pub input: Input,
+    pub value: String, // For a choice, the option picked
+    at: Option<(i32, i32)>, // Where the label goes, or None to go below the one before
+    caret: usize,
+}
+
+// The fields of a FORM drawn from a line down, one below the other unless they were given a
+// position, and the optional button that submits them
+pub struct Form {
+    pub entries: Vec<Entry>,
+    button: Option<(String, Option<(i32, i32)>)>,
+    top: i32,
+    focus: usize, // The button comes after the entries
+    message: Option<String>, // Shown below the form until the next key
+}
+
+impl Form {
+    pub fn new(top: i32) -> Form {
+        Form {
+            entries: Vec::new(),
+            button: None,
+            top,
+            focus: 0,
+            message: None,
+        }
+    }
+
+    // A choice starts on the option given as its value, or on the first one
+    pub fn add(&mut self, label: &str, input: Input, value: String, at: Option<(i32, i32)>) {
+        let value = match &input {
+            Input::Choice(options) if !options.contains(&value) => options.first().cloned().unwrap_or_default(),
+            Input::Number if value.parse::<i32>().is_err() => String::new(),
+            _ => value,
+        };
+        let caret = value.chars().count();
+        self.entries.push(Entry { label: label.to_owned(), input, value, at, caret });
+    }
+
+    pub fn button(&mut self, label: &str, at: Option<(i32, i32)>) {
+        self.button = Some((label.to_owned(), at));
+    }
+
+    fn stops(&self) -> usize {
+        self.entries.len() + self.button.is_some() as usize
+    }
+
+    // The column of the inputs of the fields laid out one below the other, lining them up
+    fn column(&self) -> i32 {
+        let width = self.entries.iter().filter(|entry| entry.at.is_none()).map(|entry| entry.label.chars().count()).max().unwrap_or(0);
+        width as i32 + 2
+    }
+
+    // Where the label and the input of each entry go
+    fn layout(&self) -> Vec<((i32, i32), (i32, i32))> {
+        let column = self.column();
+        let mut row = self.top;
+        self.entries.iter().map(|entry| match entry.at {
+            Some((y, x)) => ((y, x), (y, x + entry.label.chars().count() as i32 + 2)),
+            None => {
+                row += 1;
+                ((row - 1, 0), (row - 1, column))
+            },
+        }).collect()
+    }
+
+    // The button goes on the line after the fields, under their inputs
+    fn button_at(&self) -> Option<(i32, i32)> {
+        let (_, at) = self.button.as_ref()?;
+        let auto = self.entries.iter().filter(|entry| entry.at.is_none()).count() as i32;
+        Some(at.unwrap_or((self.top + auto + 1, self.column())))
+    }
+
+    // The first line below everything the form drew
+    pub fn bottom(&self) -> i32 {
+        let rows = self.layout().into_iter().map(|((y, _), _)| y).chain(self.button_at().map(|(y, _)| y));
+        rows.max().map(|y| y + 1).unwrap_or(self.top).max(self.top)
+    }
+
+    // Returns true once the form is submitted. Numbers have to be numbers first.
+    pub fn key(&mut self, key: Key) -> bool {
+        self.message = None;
+        let stops = self.stops();
+        if stops == 0 {
+            return key == Key::Enter;
+        }
+        match key {
+            Key::Tab | Key::Down => self.focus = (self.focus + 1) % stops,
+            Key::BackTab | Key::Up => self.focus = (self.focus + stops - 1) % stops,
+            Key::Enter => return self.submit(),
+            Key::Char(' ') if self.focus == self.entries.len() => return self.submit(),
+            key => {
+                if let Some(entry) = self.entries.get_mut(self.focus) {
+                    entry.key(key);
+                }
+            },
+        }
+        false
+    }
+
+    fn submit(&mut self) -> bool {
+        match self.entries.iter().position(|entry| entry.input == Input::Number && entry.value.parse::<i32>().is_err()) {
+            Some(invalid) => {
+                self.focus = invalid;
+                self.message = Some(format!("{} has to be a number", self.entries[invalid].label));
+                false
+            },
+            None => true,
+        }
+    }
+
+    pub fn render(&self, backend: &mut dyn Backend) {
+        let cols = backend.size().1;
+        let mut cursor = None;
+        for (i, (entry, (label, input))) in self.entries.iter().zip(self.layout()).enumerate() {
+            let focused = i == self.focus;
+            backend.move_cursor(label.0, label.1);
+            if focused {
+                backend.attribute_on(Attribute::Bold);
+            }
+            backend.print(&format!("{}:", entry.label));
+            if focused {
+                backend.attribute_off(Attribute::Bold);
+            }
+
+            let width = ((cols - input.1 - 1).max(1) as usize).min(INPUT_WIDTH);
+            backend.move_cursor(input.0, input.1);
+            if let Input::Choice(_) = entry.input {
+                if focused {
+                    backend.attribute_on(Attribute::Reverse);
+                }
+                backend.print(&format!("< {} >", entry.value));
+                if focused {
+                    backend.attribute_off(Attribute::Reverse);
+                }
+                let longest = entry.options().iter().map(|option| option.chars().count()).max().unwrap_or(0);
+                backend.print(&" ".repeat(longest - entry.value.chars().count()));
+                continue;
+            }
+
+            // Long values scroll so that the caret stays in view
+            let start = entry.caret.saturating_sub(width - 1);
+            let shown: String = match entry.input {
+                Input::Password => "*".repeat(entry.value.chars().count()),
+                _ => entry.value.clone(),
+            };
+            let shown: String = shown.chars().skip(start).take(width).collect();
+            backend.attribute_on(Attribute::Underline);
+            backend.print(&format!("{:<1$}", shown, width));
+            backend.attribute_off(Attribute::Underline);
+            if focused {
+                cursor = Some((input.0, input.1 + (entry.caret - start) as i32));
+            }
+        }
+
+        if let (Some((label, _)), Some((y, x))) = (&self.button, self.button_at()) {
+            let focused = self.focus == self.entries.len();
+            backend.move_cursor(y, x);
+            if focused {
+                backend.attribute_on(Attribute::Reverse);
+            }
+            backend.print(&format!("[ {} ]", label));
+            if focused {
+                backend.attribute_off(Attribute::Reverse);
+            }
+        }
+
+        backend.move_cursor(self.bottom().min(backend.size().0 - 1), 0);
+        let message = self.message.as_deref().unwrap_or("");
+        backend.print(&format!("{:<1$}", message, (cols - 1).max(0) as usize));
+
+        backend.set_cursor_visible(cursor.is_some());
+        if let Some((y, x)) = cursor {
+            backend.move_cursor(y, x);
+        }
+        backend.refresh();
+    }
+}
+
+impl Entry {
+    fn options(&self) -> &[String] {
+        match &self.input {
+            Input::Choice(options) => options,
+            _ => &[],
+        }
+    }
+
+    // Edits the value in place; a choice goes through its options instead
+    fn key(&mut self, key: Key) {
+        if let Input::Choice(options) = &self.input {
+            let count = options.len().max(1);
+            let current = options.iter().position(|option| *option == self.value).unwrap_or(0);
+            let next = match key {
+                Key::Right | Key::Char(' ') => (current + 1) % count,
+                Key::Left => (current + count - 1) % count,
+                _ => return,
+            };
+            self.value = options.get(next).cloned().unwrap_or_default();
+            return;
+        }
+
+        let mut chars: Vec<char> = self.value.chars().collect();
+        match key {
+            // A number only takes digits, after a minus at its start
+            Key::Char(c) if self.input == Input::Number && !(c.is_ascii_digit() || (c == '-' && self.caret == 0 && !chars.contains(&'-'))) => {},
+            Key::Char(c) => {
+                chars.insert(self.caret, c);
+                self.caret += 1;
+            },
+            Key::Backspace if self.caret > 0 => {
+                self.caret -= 1;
+                chars.remove(self.caret);
+            },
+            Key::Delete if self.caret < chars.len() => {
+                chars.remove(self.caret);
+            },
+            Key::Left => self.caret = self.caret.saturating_sub(1),
+            Key::Right => self.caret = (self.caret + 1).min(chars.len()),
+            Key::Home => self.caret = 0,
+            Key::End => self.caret = chars.len(),
+            _ => {},
+        }
+        self.value = chars.into_iter().collect();
+    }
+}
+
+#[test]
+fn form_test() {
+    use crate::backend::VirtualScreen;
+
+    let mut screen = VirtualScreen::new(8, 40);
+    let mut form = Form::new(1);
+    form.add("Name", Input::Text, "Ada".to_owned(), None);
+    form.add("Age", Input::Number, String::new(), None);
+    form.add("PIN", Input::Password, String::new(), Some((6, 20)));
+    form.add("Editor", Input::Choice(vec!["vi".to_owned(), "emacs".to_owned()]), "emacs".to_owned(), None);
+    form.button("Save", None);
+    form.render(&mut screen);
+    assert_eq!(screen.row(1).trim_end(), "Name:   Ada");
+    assert_eq!(screen.row(3).trim_end(), "Editor: < emacs >");
+    assert_eq!(screen.row(5).trim_end(), "        [ Save ]");
+    assert_eq!(screen.cursor(), (1, 11));
+    assert!(screen.cell(1, 8).has(Attribute::Underline));
+
+    // Numbers only take digits, and have to be given before the form is submitted
+    for key in [Key::Backspace, Key::Tab, Key::Char('4'), Key::Char('x'), Key::Char('2'), Key::Left, Key::Char('-')].iter() {
+        assert!(!form.key(*key));
+    }
+    assert_eq!(form.entries[1].value, "42");
+    form.key(Key::Delete);
+    form.key(Key::Backspace);
+    assert!(!form.key(Key::Enter));
+    form.render(&mut screen);
+    assert_eq!(screen.row(7).trim_end(), "Age has to be a number");
+    assert_eq!(screen.cursor(), (2, 8));
+
+    for key in [Key::Char('7'), Key::Tab, Key::Char('1'), Key::Tab, Key::Left, Key::Tab].iter() {
+        assert!(!form.key(*key));
+    }
+    form.render(&mut screen);
+    assert_eq!(screen.row(6).trim_end(), "                    PIN: *");
+    assert!(screen.cell(5, 8).has(Attribute::Reverse));
+    assert!(form.key(Key::Char(' ')));
+    let values: Vec<&str> = form.entries.iter().map(|entry| entry.value.as_str()).collect();
+    assert_eq!(values, vec!["Ad", "7", "1", "vi"]);
+}
@@ -0,0 +1,56 @@
+use std::collections::HashMap;
+
+use crate::interpreter::Variable;
+
+// A function the host provides to scripts. It is given the values of the arguments and either
+// returns a value, which CALL can store with INTO, or explains what went wrong.
+pub type Function = Box<dyn FnMut(&[Variable]) -> Result<Variable, String>>;
+
+// Functions every interpreter starts with, so that scripts run from the command line can use
+// them too
+pub fn builtins() -> HashMap<String, Function> {
+    let mut res: HashMap<String, Function> = HashMap::new();
+    res.insert("version".to_owned(), Box::new(|arguments: &[Variable]| {
+        if !arguments.is_empty() {
+            return Err("takes no arguments".to_owned());
+        }
+        Ok(Variable::Str(env!("CARGO_PKG_VERSION").to_owned()))
+    }));
+    res.insert("len".to_owned(), Box::new(|arguments: &[Variable]| match arguments {
+        [Variable::List(items)] => Ok(Variable::Num(items.len() as i32)),
+        [Variable::Str(s)] => Ok(Variable::Num(s.chars().count() as i32)),
+        [Variable::Map(fields)] => Ok(Variable::Num(fields.len() as i32)),
+        _ => Err("expects a list, a string or a map".to_owned()),
+    }));
+    res.insert("keys".to_owned(), Box::new(|arguments: &[Variable]| match arguments {
+        [Variable::Map(fields)] => Ok(Variable::List(fields.keys().map(|key| Variable::Str(key.to_owned())).collect())),
+        _ => Err("expects a map".to_owned()),
+    }));
+    // Items that are not strings are joined the way traces show them
+    res.insert("join".to_owned(), Box::new(|arguments: &[Variable]| match arguments {
+        [Variable::List(items), Variable::Str(separator)] => {
+            let items: Vec<String> = items.iter().map(|item| match item {
+                Variable::Str(s) => s.to_owned(),
+                other => other.to_string(),
+            }).collect();
+            Ok(Variable::Str(items.join(separator)))
+        },
+        _ => Err("expects a list and a separator".to_owned()),
+    }));
+    res.insert("split".to_owned(), Box::new(|arguments: &[Variable]| match arguments {
+        [Variable::Str(s), Variable::Str(separator)] if !separator.is_empty() => {
+            Ok(Variable::List(s.split(separator.as_str()).map(|item| Variable::Str(item.to_owned())).collect()))
+        },
+        _ => Err("expects a string and a separator".to_owned()),
+    }));
+    // Scripts cannot write a line break, so the output of EXEC is split with this instead
+    res.insert("lines".to_owned(), Box::new(|arguments: &[Variable]| match arguments {
+        [Variable::Str(s)] => Ok(Variable::List(s.lines().map(|line| Variable::Str(line.to_owned())).collect())),
+        _ => Err("expects a string".to_owned()),
+    }));
+    res
+}
+
+pub fn builtin_names() -> Vec<String> {
+    builtins().keys().cloned().collect()
+}
@@ -0,0 +1,149 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::diagnostic::{Diagnostic, Span};
+use crate::error::PzxError;
+use crate::lexer::derive_tokens;
+use crate::parser::{parse_tokens, Operand, Program, Statement, StatementKind};
+
+// Replaces every INCLUDE with the statements of the file it names, which is found relative to
+// the including script, or to the current directory for instructions that came from no file.
+// A file is only included once however many scripts include it, and a file that ends up
+// including itself is an error.
+pub fn expand(program: Program, script: Option<&Path>) -> Result<Program, PzxError> {
+    let mut expander = Expander {
+        included: HashSet::new(),
+        stack: Vec::new(),
+        files: Vec::new(),
+    };
+
+    let base = match script.and_then(|script| script.canonicalize().ok()) {
+        Some(script) => {
+            expander.included.insert(script.clone());
+            expander.stack.push(script.clone());
+            script.parent().map(Path::to_path_buf).unwrap_or_default()
+        },
+        None => PathBuf::from("."),
+    };
+
+    let statements = expander.statements(program.statements, &base)?;
+    Ok(Program { statements, files: expander.files })
+}
+
+struct Expander {
+    included: HashSet<PathBuf>,
+    // The files being included right now, outermost first
+    stack: Vec<PathBuf>,
+    files: Vec<String>,
+}
+
+impl Expander {
+    fn statements(&mut self, statements: Vec<Statement>, base: &Path) -> Result<Vec<Statement>, PzxError> {
+        let mut res = Vec::new();
+        for statement in statements {
+            let Statement { operator, kind, span } = statement;
+            let kind = match kind {
+                StatementKind::Include(path) => {
+                    res.extend(self.file(&path, base)?);
+                    continue;
+                },
+                StatementKind::On(event, body) => StatementKind::On(event, self.statements(body, base)?),
+                StatementKind::Proc(name, parameters, body) => StatementKind::Proc(name, parameters, self.statements(body, base)?),
+                StatementKind::For(item, list, body) => StatementKind::For(item, list, self.statements(body, base)?),
+                StatementKind::If(condition, body, otherwise) => {
+                    StatementKind::If(condition, self.statements(body, base)?, self.statements(otherwise, base)?)
+                },
+                kind => kind,
+            };
+            res.push(Statement { operator, kind, span });
+        }
+        Ok(res)
+    }
+
+    fn file(&mut self, operand: &Operand, base: &Path) -> Result<Vec<Statement>, PzxError> {
+        let span = operand.span();
+        let name = match operand {
+            Operand::Literal(name, ..) => name,
+            _ => unreachable!("the parser only lets literals through as INCLUDE paths"),
+        };
+
+        let path = base.join(name).canonicalize().map_err(|e| error(span, format!("Could not include {}: {}", name, e)))?;
+        if let Some(start) = self.stack.iter().position(|included| *included == path) {
+            let cycle: Vec<String> = self.stack[start..].iter().chain(Some(&path)).map(|included| file_name(included)).collect();
+            return Err(error(span, format!("Include cycle: {}", cycle.join(" -> "))));
+        }
+        if !self.included.insert(path.clone()) {
+            return Ok(Vec::new());
+        }
+
+        let raw = fs::read_to_string(&path).map_err(|e| error(span, format!("Could not include {}: {}", name, e)))?;
+        // The statements' spans say which file they came from, so that problems found once the
+        // file has been parsed can name it. Problems parsing it are reported at the INCLUDE.
+        self.files.push(display(&path));
+        let file = self.files.len();
+        let mut tokens = derive_tokens(&raw).map_err(|e| within(PzxError::Parse(vec![e]), name, span))?;
+        for (_, token_span) in tokens.iter_mut() {
+            token_span.file = file;
+        }
+        let program = parse_tokens(&tokens).map_err(|e| within(PzxError::Parse(e), name, span))?;
+
+        self.stack.push(path.clone());
+        let res = self.statements(program.statements, path.parent().unwrap_or(base));
+        self.stack.pop();
+        res.map_err(|e| within(e, name, span))
+    }
+}
+
+fn error(span: Span, message: String) -> PzxError {
+    PzxError::Parse(vec![Diagnostic::error(span, message)])
+}
+
+fn within(e: PzxError, name: &str, span: Span) -> PzxError {
+    PzxError::Parse(e.diagnostics().iter().map(|diagnostic| Diagnostic::error(span, format!("In {}: {}", name, diagnostic))).collect())
+}
+
+// Relative to the current directory where possible, the way the script itself was named
+fn display(path: &Path) -> String {
+    let relative = std::env::current_dir().ok().and_then(|dir| path.strip_prefix(dir).ok().map(Path::to_path_buf));
+    relative.unwrap_or_else(|| path.to_path_buf()).display().to_string()
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_else(|| path.display().to_string())
+}
+
+#[test]
+fn include_test() {
+    let dir = std::env::temp_dir().join(format!("pzx-include-test-{}", std::process::id()));
+    fs::create_dir_all(dir.join("shared")).unwrap();
+    fs::write(dir.join("shared/colors.pzx"), "CLR accent 0 500 1000").unwrap();
+    fs::write(dir.join("shared/banner.pzx"), "INCLUDE \"colors.pzx\"; PROC banner; FORE accent; END").unwrap();
+    fs::write(dir.join("main.pzx"), "").unwrap();
+    fs::write(dir.join("a.pzx"), "INCLUDE \"b.pzx\"").unwrap();
+    fs::write(dir.join("b.pzx"), "PRINT \"b\";\nINCLUDE \"a.pzx\"").unwrap();
+
+    let main = dir.join("main.pzx");
+    let program = Program::parse("INCLUDE \"shared/banner.pzx\"; INCLUDE \"shared/colors.pzx\"; CALL banner").unwrap();
+    let program = expand(program, Some(&main)).unwrap();
+    let operators: Vec<&str> = program.statements.iter().map(|statement| statement.operator.name()).collect();
+    assert_eq!(operators, vec!["CLR", "PROC", "CALL"]);
+
+    let error = expand(Program::parse("INCLUDE \"a.pzx\"").unwrap(), Some(&main)).unwrap_err();
+    assert_eq!(error.to_string(), "1:9: error: In a.pzx: 1:9: error: In b.pzx: 2:9: error: Include cycle: a.pzx -> b.pzx -> a.pzx");
+    let error = expand(Program::parse("INCLUDE \"missing.pzx\"").unwrap(), Some(&main)).unwrap_err();
+    assert!(error.to_string().starts_with("1:9: error: Could not include missing.pzx: "));
+
+    // Problems found in an included file once it has been parsed name that file
+    fs::write(dir.join("common.pzx"), "PROC bad;\n  MOVE \"a\" 0;\nEND").unwrap();
+    let program = expand(Program::parse("INCLUDE \"common.pzx\";\nCALL bad").unwrap(), Some(&main)).unwrap();
+    let common = dir.join("common.pzx").canonicalize().unwrap().display().to_string();
+    let found = crate::check::check(&program);
+    assert_eq!(found[0].to_string(), "2:8: error: Type mismatch: MOVE expects a number but `a` is not one");
+    assert_eq!(program.file(found[0].span), Some(common.as_str()));
+    let mut screen = crate::backend::VirtualScreen::new(10, 40);
+    let error = crate::interpreter::Interpreter::new(&mut screen).run(&program).unwrap_err();
+    let diagnostic = &error.diagnostics()[0];
+    assert_eq!((program.file(diagnostic.span), diagnostic.span.line, diagnostic.span.column), (Some(common.as_str()), 2, 8));
+    fs::remove_dir_all(&dir).unwrap();
+}
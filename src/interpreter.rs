@@ -0,0 +1,1680 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender, TryRecvError};
+use std::thread;
+use std::time::Duration;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::backend::{Backend, Key};
+use crate::check;
+use crate::debug::Debugger;
+use crate::diagnostic::{Diagnostic, Span};
+use crate::dump;
+use crate::error::PzxError;
+use crate::form::{Form, Input};
+use crate::functions::{self, Function};
+use crate::keys::Replay;
+use crate::lexer::Comparison;
+use crate::pager::Pager;
+use crate::parser::{Button, ColorOperand, Field, FieldKind, GaugeSource, Operand, Program, Statement, StatementKind, TailSource};
+use crate::progress::{self, GaugeInput};
+use crate::snapshot::{self, Format};
+use crate::tail::Tail;
+use crate::trace::{self, Trace};
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum Variable {
+    Num(i32),
+    Str(String),
+    Clr {
+        r: i16,
+        g: i16,
+        b: i16,
+    },
+    List(Vec<Variable>),
+    Map(BTreeMap<String, Variable>), // Kept in order of their keys
+    Bool(bool),
+}
+
+// How values appear in traces
+impl fmt::Display for Variable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Variable::Num(i) => write!(f, "{}", i),
+            Variable::Str(s) => write!(f, "{:?}", s),
+            Variable::Clr{r, g, b} => write!(f, "rgb({}, {}, {})", r, g, b),
+            Variable::List(items) => {
+                let items: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+                write!(f, "[{}]", items.join(", "))
+            },
+            Variable::Map(fields) => {
+                let fields: Vec<String> = fields.iter().map(|(key, value)| format!("{}: {}", key, value)).collect();
+                write!(f, "{{{}}}", fields.join(", "))
+            },
+            Variable::Bool(true) => write!(f, "TRUE"),
+            Variable::Bool(false) => write!(f, "FALSE"),
+        }
+    }
+}
+
+impl Variable {
+    // Whether a value counts as true where a condition is expected: numbers other than 0, and
+    // strings, lists and maps that are not empty. Colors always do.
+    pub fn truthy(&self) -> bool {
+        match self {
+            Variable::Bool(value) => *value,
+            Variable::Num(i) => *i != 0,
+            Variable::Str(s) => !s.is_empty(),
+            Variable::List(items) => !items.is_empty(),
+            Variable::Map(fields) => !fields.is_empty(),
+            Variable::Clr{..} => true,
+        }
+    }
+
+    // Colors become objects of their three components
+    pub fn to_json(&self) -> String {
+        match self {
+            Variable::Num(i) => i.to_string(),
+            Variable::Str(s) => dump::quote(s),
+            Variable::Clr{r, g, b} => format!("{{\"r\":{},\"g\":{},\"b\":{}}}", r, g, b),
+            Variable::List(items) => {
+                let items: Vec<String> = items.iter().map(Variable::to_json).collect();
+                format!("[{}]", items.join(","))
+            },
+            Variable::Map(fields) => {
+                let fields: Vec<String> = fields.iter().map(|(key, value)| format!("{}:{}", dump::quote(key), value.to_json())).collect();
+                format!("{{{}}}", fields.join(","))
+            },
+            Variable::Bool(value) => value.to_string(),
+        }
+    }
+
+    // How errors name the type of a value
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Variable::Num(_) => "a number",
+            Variable::Str(_) => "a string",
+            Variable::Clr{..} => "a color",
+            Variable::List(_) => "a list",
+            Variable::Map(_) => "a map",
+            Variable::Bool(_) => "a bool",
+        }
+    }
+}
+
+// What a program left behind once it ended
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct Outcome {
+    pub out: String,
+    pub variables: HashMap<String, Variable>,
+    pub stopped: bool, // Whether the debugger stopped the program before its end
+}
+
+// A procedure a script defined with PROC
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub(crate) struct Procedure {
+    parameters: Vec<String>,
+    body: Vec<Statement>,
+}
+
+// How deeply procedures may call one another before a script is taken to recurse without end
+pub const MAX_DEPTH: usize = 64;
+
+// Runs programs against a backend. Variables, handlers and saved positions are kept from one
+// program to the next, so a host can set variables, run a program and read back what it left.
+pub struct Interpreter<'a> {
+    pub(crate) backend: &'a mut dyn Backend,
+    pub(crate) variables: HashMap<String, Variable>,
+    pub(crate) handlers: HashMap<String, Vec<Statement>>,
+    pub(crate) positions: HashMap<String, (i32, i32)>,
+    pub(crate) functions: HashMap<String, Function>,
+    pub(crate) procedures: HashMap<String, Procedure>,
+    // The local variables of every procedure running, innermost last
+    pub(crate) frames: Vec<HashMap<String, Variable>>,
+    // The names declared with CONST, globally first and then in every running procedure
+    pub(crate) constants: Vec<HashSet<String>>,
+    // Variables given from outside the script, which its declarations do not replace
+    pub(crate) defined: HashSet<String>,
+    pub(crate) returning: Option<Variable>, // Set by RETURN until the procedure has ended
+    pub(crate) replay: Option<Replay>,
+    pub(crate) trace: Option<Trace>,
+    pub(crate) debugger: Option<Debugger>,
+    pub(crate) stopped: bool, // Set when the debugger stops the program
+}
+
+impl<'a> Interpreter<'a> {
+    // Sets up the terminal's colors and the built-in variables
+    pub fn new(backend: &'a mut dyn Backend) -> Interpreter<'a> {
+        let variables = global_variables(backend);
+        Interpreter {
+            backend,
+            variables,
+            handlers: HashMap::new(),
+            positions: HashMap::new(),
+            functions: functions::builtins(),
+            procedures: HashMap::new(),
+            frames: Vec::new(),
+            constants: vec![HashSet::new()],
+            defined: HashSet::new(),
+            returning: None,
+            replay: None,
+            trace: None,
+            debugger: None,
+            stopped: false,
+        }
+    }
+
+    pub fn set_var(&mut self, name: &str, value: Variable) {
+        self.variables.insert(name.to_owned(), value);
+    }
+
+    pub fn get_var(&self, name: &str) -> Option<&Variable> {
+        self.variables.get(name)
+    }
+
+    // Sets a variable the way `-D name=value` does. A LET or CONST of it in the script keeps this
+    // value, converted to the declared type, so what the script declares serves as a default.
+    // Until then it is a string.
+    pub fn define(&mut self, name: &str, value: &str) {
+        self.variables.insert(name.to_owned(), Variable::Str(value.to_owned()));
+        self.defined.insert(name.to_owned());
+    }
+
+    // The arguments given after the instructions, as the list `args` and as `$1`, `$2` and so on
+    pub fn set_args(&mut self, args: &[String]) {
+        for (i, arg) in args.iter().enumerate() {
+            self.variables.insert(format!("${}", i + 1), Variable::Str(arg.to_owned()));
+        }
+        self.variables.insert("args".to_owned(), Variable::List(args.iter().map(|arg| Variable::Str(arg.to_owned())).collect()));
+    }
+
+    // Makes a Rust function callable from scripts, replacing any function of the same name
+    pub fn register_fn<F>(&mut self, name: &str, function: F)
+        where F: FnMut(&[Variable]) -> Result<Variable, String> + 'static {
+        self.functions.insert(name.to_owned(), Box::new(function));
+    }
+
+    // The running procedure's variables shadow the global ones; other procedures' are out of
+    // reach. A name such as `cfg.port` reaches into the fields of maps.
+    pub(crate) fn lookup(&self, name: &str) -> Option<&Variable> {
+        let mut path = name.split('.');
+        let base = path.next()?;
+        let mut res = self.frames.last().and_then(|frame| frame.get(base)).or_else(|| self.variables.get(base))?;
+        for field in path {
+            match res {
+                Variable::Map(fields) => res = fields.get(field)?,
+                _ => return None,
+            }
+        }
+        Some(res)
+    }
+
+    fn lookup_mut(&mut self, name: &str) -> Option<&mut Variable> {
+        let mut path = name.split('.');
+        let base = path.next()?;
+        let mut res = match self.frames.last_mut() {
+            Some(frame) if frame.contains_key(base) => frame.get_mut(base)?,
+            _ => self.variables.get_mut(base)?,
+        };
+        for field in path {
+            match res {
+                Variable::Map(fields) => res = fields.get_mut(field)?,
+                _ => return None,
+            }
+        }
+        Some(res)
+    }
+
+    // Inside a procedure new variables are local to it, while global ones are changed in place
+    pub(crate) fn assign(&mut self, name: String, value: Variable) {
+        match self.frames.last_mut() {
+            Some(frame) if frame.contains_key(&name) || !self.variables.contains_key(&name) => {
+                frame.insert(name, value);
+            },
+            _ => {
+                self.variables.insert(name, value);
+            },
+        }
+    }
+
+    // Changes a variable the way SET does, for the debugger
+    pub(crate) fn set(&mut self, name: &str, value: Variable) -> Result<(), String> {
+        let target = Operand::Identifier(name.to_owned(), Span::default());
+        set(self, &target, value).map_err(|err| err.diagnostics()[0].message.clone())
+    }
+
+    // Whether a name refers to a constant, in whichever scope it is found
+    fn constant(&self, name: &str) -> bool {
+        match self.frames.last() {
+            Some(frame) if frame.contains_key(name) => self.constants.last().is_some_and(|names| names.contains(name)),
+            _ => self.constants[0].contains(name),
+        }
+    }
+
+    // Every variable the running statement can see
+    pub(crate) fn visible(&self) -> HashMap<String, Variable> {
+        let mut res = self.variables.clone();
+        if let Some(frame) = self.frames.last() {
+            res.extend(frame.clone());
+        }
+        res
+    }
+
+    // Checks a program statically, knowing about every function registered so far
+    pub fn check(&self, program: &Program) -> Vec<Diagnostic> {
+        let names: Vec<String> = self.functions.keys().cloned().collect();
+        check::check_with(program, &names)
+    }
+
+    // Input is read from these keys instead of the backend
+    pub fn set_replay(&mut self, replay: Replay) {
+        self.replay = Some(replay);
+    }
+
+    pub fn set_trace(&mut self, trace: Trace) {
+        self.trace = Some(trace);
+    }
+
+    pub fn set_debugger(&mut self, debugger: Debugger) {
+        self.debugger = Some(debugger);
+    }
+
+    pub fn run(&mut self, program: &Program) -> Result<Outcome, PzxError> {
+        execute(&program.statements, self)?;
+        Ok(Outcome {
+            out: self.output(),
+            variables: self.variables.clone(),
+            stopped: self.stopped,
+        })
+    }
+
+    // What a program leaves in `out`, which is printed once it ends
+    // Lists and maps are printed as JSON
+    pub fn output(&self) -> String {
+        match self.variables.get("out") {
+            Some(Variable::Str(s)) => s.to_owned(),
+            Some(value @ Variable::List(_)) | Some(value @ Variable::Map(_)) => value.to_json(),
+            _ => "".to_owned(),
+        }
+    }
+}
+
+pub(crate) fn execute(statements: &[Statement], state: &mut Interpreter) -> Result<(), PzxError> {
+    for statement in statements.iter() {
+        // The debugger is taken out of the state while it pauses, since it may change the state
+        if let Some(mut debugger) = state.debugger.take() {
+            if !debugger.pause(statement, state) {
+                state.stopped = true;
+            }
+            state.debugger = Some(debugger);
+        }
+        if state.stopped {
+            return Ok(());
+        }
+
+        // Only a trace needs to know what a statement changed
+        let before = state.trace.as_ref().map(|_| state.visible());
+        execute_statement(statement, state)?;
+        if let (Some(trace), Some(before)) = (&state.trace, before) {
+            trace.info(&trace::statement(statement, &before, &state.visible()));
+        }
+
+        // RETURN ends the procedure, however deeply inside it the statement was
+        if state.returning.is_some() {
+            return Ok(());
+        }
+    }
+
+    Ok(())
+}
+
+fn execute_statement(statement: &Statement, state: &mut Interpreter) -> Result<(), PzxError> {
+    let name = statement.operator.name();
+    match &statement.kind {
+        StatementKind::Print(value) => {
+            match value {
+                Operand::Literal(s, ..) => {
+                    state.backend.print(s);
+                },
+                Operand::Identifier(var, span) => {
+                    match state.lookup(var).cloned() {
+                        Some(Variable::Str(s)) => state.backend.print(&s),
+                        Some(_) => {},
+                        None => return Err(undefined(var, *span)),
+                    }
+                },
+                _ => {
+                    if let Variable::Str(s) = evaluate(state, value)? {
+                        state.backend.print(&s);
+                    }
+                },
+            }
+            sync_cursor(state);
+        },
+        StatementKind::Await(target) => {
+            let operand = read_line(state)?;
+            store(state, target, Variable::Str(operand))?;
+            sync_cursor(state);
+        },
+        StatementKind::Accept(target) => {
+            state.backend.set_cursor_visible(true);
+            let mut key = read_key(state);
+            while key == Key::Resize {
+                handle_resize(state)?;
+                key = read_key(state);
+            }
+            // The key is echoed the way ncurses would echo it
+            let operand = key.to_char().map(|c| c.to_string()).unwrap_or_default();
+            if let Key::Char(c) = key {
+                state.backend.print(&c.to_string());
+            }
+            state.backend.set_cursor_visible(false);
+            store(state, target, Variable::Str(operand))?;
+            sync_cursor(state);
+        },
+        StatementKind::Foreground(color) => {
+            let color = color_value(state, color, name)?;
+            change_foreground(state.backend, &color).expect("Err: Could not change foreground color");
+        },
+        StatementKind::Background(color) => {
+            let color = color_value(state, color, name)?;
+            change_background(state.backend, &color).expect("Err: Could not change background color");
+        },
+        StatementKind::Move(line, column) => {
+            // First get the desired position
+            let pos = (number(state, line, name)?, number(state, column, name)?);
+            // Then move to it
+            state.backend.move_cursor(pos.0, pos.1);
+            sync_cursor(state);
+        },
+        StatementKind::Shift(line, column) => {
+            // First get the desired position
+            let mut pos = (number(state, line, name)?, number(state, column, name)?);
+            // Shift as offset by the real cursor position, which output may have moved
+            let (y, x) = state.backend.cursor();
+            pos.0 += y;
+            pos.1 += x;
+            // Then move to it
+            state.backend.move_cursor(pos.0, pos.1);
+            sync_cursor(state);
+        },
+        StatementKind::StringAssign(target, value) => {
+            let value = text(state, value, name)?;
+            declared(state, target, name)?;
+            store(state, target, Variable::Str(value))?;
+        },
+        StatementKind::NumberAssign(target, value) => {
+            let value = number(state, value, name)?;
+            declared(state, target, name)?;
+            store(state, target, Variable::Num(value))?;
+        },
+        StatementKind::ColorAssign(target, r, g, b) => {
+            let color = Variable::Clr {
+                r: component(r, name)?,
+                g: component(g, name)?,
+                b: component(b, name)?,
+            };
+            store(state, target, color)?;
+        },
+        StatementKind::On(event, body) => {
+            let event_name = variable_name(event);
+            if event_name != "RESIZE" {
+                return Err(PzxError::runtime(event.span(), format!("Unknown event `{}` provided to ON operator", event_name)));
+            }
+
+            // Handlers are only stored here; they run when their event fires
+            state.handlers.insert(event_name, body.clone());
+        },
+        StatementKind::SavePosition(target) => {
+            let position = state.backend.cursor();
+            state.positions.insert(variable_name(target), position);
+        },
+        StatementKind::RestorePosition(target) => {
+            let (y, x) = match state.positions.get(&variable_name(target)) {
+                Some(position) => *position,
+                None => return Err(PzxError::runtime(target.span(), "RESTOREPOS provided a position that was never saved".to_owned())),
+            };
+            state.backend.move_cursor(y, x);
+            sync_cursor(state);
+        },
+        StatementKind::Snapshot(path, format) => {
+            let span = path.span();
+            let path = text(state, path, name)?;
+
+            // Without a FORMAT the file extension decides
+            let format = match format {
+                Some(format) => match Format::from_name(&variable_name(format)) {
+                    Some(format) => format,
+                    None => return Err(PzxError::runtime(format.span(), "Unknown format provided to SNAPSHOT operator".to_owned())),
+                },
+                None => Format::from_path(&path),
+            };
+
+            snapshot::write(&state.backend.snapshot(), &path, format)
+                .map_err(|e| PzxError::runtime(span, format!("Could not write snapshot to {}: {}", path, e)))?;
+        },
+        StatementKind::Call(function, arguments, target) => {
+            let mut values = Vec::new();
+            for argument in arguments.iter() {
+                values.push(evaluate(state, argument)?);
+            }
+            let result = call(state, &variable_name(function), &values, function.span())?;
+            if let Some(target) = target {
+                store(state, target, result)?;
+            }
+        },
+        StatementKind::Proc(procedure, parameters, body) => {
+            // Like handlers, procedures are only stored here; they run when they are called
+            let procedure_name = variable_name(procedure);
+            state.procedures.insert(procedure_name, Procedure {
+                parameters: parameters.iter().map(variable_name).collect(),
+                body: body.clone(),
+            });
+        },
+        StatementKind::Return(value) => {
+            if state.frames.is_empty() {
+                return Err(PzxError::runtime(statement.span, "RETURN provided outside of a procedure".to_owned()));
+            }
+            let value = match value {
+                Some(value) => evaluate(state, value)?,
+                None => Variable::Str(String::new()),
+            };
+            state.returning = Some(value);
+        },
+        StatementKind::Include(_) => {
+            return Err(PzxError::runtime(statement.span, "INCLUDE must be expanded with include::expand before the program runs".to_owned()));
+        },
+        StatementKind::BoolAssign(target, value) => {
+            let value = evaluate(state, value)?.truthy();
+            store(state, target, Variable::Bool(value))?;
+        },
+        StatementKind::Confirm(target) => {
+            // Only y and n answer; Enter, like running out of input, takes the safe answer
+            state.backend.set_cursor_visible(true);
+            let answer = loop {
+                match read_key(state) {
+                    Key::Resize => handle_resize(state)?,
+                    Key::Char('y') | Key::Char('Y') => break true,
+                    Key::Char('n') | Key::Char('N') | Key::Enter | Key::Eof => break false,
+                    _ => {},
+                }
+            };
+            state.backend.print(if answer { "y" } else { "n" });
+            state.backend.set_cursor_visible(false);
+            store(state, target, Variable::Bool(answer))?;
+            sync_cursor(state);
+        },
+        StatementKind::If(condition, body, otherwise) => {
+            if evaluate(state, condition)?.truthy() {
+                execute(body, state)?;
+            } else {
+                execute(otherwise, state)?;
+            }
+        },
+        StatementKind::MapAssign(target, value) => {
+            let fields = match evaluate(state, value)? {
+                map @ Variable::Map(_) => map,
+                _ => return Err(improper(value, name)),
+            };
+            store(state, target, fields)?;
+        },
+        StatementKind::ListAssign(target, value) => {
+            let items = match evaluate(state, value)? {
+                list @ Variable::List(_) => list,
+                _ => return Err(improper(value, name)),
+            };
+            store(state, target, items)?;
+        },
+        StatementKind::Push(target, value) => {
+            let value = evaluate(state, value)?;
+            writable(state, target)?;
+            match state.lookup_mut(&variable_name(target)) {
+                Some(Variable::List(items)) => items.push(value),
+                _ => return Err(improper(target, name)),
+            }
+        },
+        StatementKind::Pop(list, target) => {
+            writable(state, list)?;
+            let item = match state.lookup_mut(&variable_name(list)) {
+                Some(Variable::List(items)) => items.pop(),
+                _ => return Err(improper(list, name)),
+            };
+            match item {
+                Some(item) => store(state, target, item)?,
+                None => return Err(PzxError::runtime(list.span(), format!("POP provided the empty list `{}`", variable_name(list)))),
+            }
+        },
+        StatementKind::For(item, list, body) => {
+            // Maps are gone over by their keys
+            let items = match evaluate(state, list)? {
+                Variable::List(items) => items,
+                Variable::Map(fields) => fields.keys().map(|key| Variable::Str(key.to_owned())).collect(),
+                _ => return Err(improper(list, name)),
+            };
+            // The loop goes over the list as it was when the loop began
+            // The item may change its type from one run of the body to the next
+            for value in items {
+                put(state, item, value)?;
+                execute(body, state)?;
+                if state.returning.is_some() || state.stopped {
+                    break;
+                }
+            }
+        },
+        StatementKind::Let(target, value) | StatementKind::Const(target, value) => {
+            let value = evaluate(state, value)?;
+            let constant = matches!(statement.kind, StatementKind::Const(..));
+            declare(state, target, value, constant)?;
+        },
+        // Variables that are not set, or not valid unicode, take the default
+        StatementKind::Env(target, variable, default) => {
+            let value = match std::env::var(text(state, variable, name)?) {
+                Ok(value) => value,
+                Err(_) => match default {
+                    Some(default) => text(state, default, name)?,
+                    None => String::new(),
+                },
+            };
+            store(state, target, Variable::Str(value))?;
+        },
+        StatementKind::Exec(..) | StatementKind::Shell(..) | StatementKind::Spin(..) | StatementKind::Gauge(..) | StatementKind::Tail(..) | StatementKind::Pager(..) | StatementKind::Form(..) => {
+            execute_command(statement, state)?;
+        },
+        StatementKind::Set(target, value) => {
+            let value = evaluate(state, value)?;
+            set(state, target, value)?;
+        },
+    }
+
+    Ok(())
+}
+
+fn undefined(name: &str, span: Span) -> PzxError {
+    PzxError::runtime(span, format!("`{}` is not defined", name))
+}
+
+fn improper(operand: &Operand, operator: &str) -> PzxError {
+    PzxError::runtime(operand.span(), format!("Improper operand provided to {} operator", operator))
+}
+
+// SET only changes variables that were declared, and a field of a map such as `cfg.port`
+fn set(state: &mut Interpreter, target: &Operand, value: Variable) -> Result<(), PzxError> {
+    let var = variable_name(target);
+    let base = var.split('.').next().unwrap_or_default();
+    if state.lookup(base).is_none() {
+        return Err(PzxError::runtime(target.span(), format!("`{}` is not declared; use LET", base)));
+    }
+    store(state, target, value)
+}
+
+// LET and CONST create a variable in the running procedure, or globally outside of one
+fn declare(state: &mut Interpreter, target: &Operand, value: Variable, constant: bool) -> Result<(), PzxError> {
+    let name = variable_name(target);
+    if let Some(changed) = check::built_in(&name) {
+        return Err(PzxError::runtime(target.span(), format!("`{}` is built in and {}", name, changed)));
+    }
+    if name.contains('.') {
+        return Err(PzxError::runtime(target.span(), format!("`{}` is a field, which cannot be declared; use SET", name)));
+    }
+
+    let declared = match state.frames.last() {
+        Some(frame) => frame.contains_key(&name),
+        None => state.variables.contains_key(&name),
+    };
+    // A variable defined from outside keeps its value, as long as it has the declared type
+    if declared && state.frames.is_empty() && state.defined.remove(&name) {
+        let given = match &state.variables[&name] {
+            Variable::Str(s) => s.clone(),
+            other => other.to_string(),
+        };
+        let converted = match value {
+            Variable::Str(_) => Variable::Str(given),
+            Variable::Num(_) => match given.parse() {
+                Ok(n) => Variable::Num(n),
+                Err(_) => return Err(PzxError::runtime(target.span(), format!("`{}` was given as {:?}, but is declared as a number", name, given))),
+            },
+            Variable::Bool(_) if given == "TRUE" || given == "FALSE" => Variable::Bool(given == "TRUE"),
+            _ => {
+                let message = format!("`{}` was given as {:?}, but is declared as {}", name, given, value.type_name());
+                return Err(PzxError::runtime(target.span(), message));
+            },
+        };
+        state.variables.insert(name.clone(), converted);
+        if constant {
+            state.constants[0].insert(name);
+        }
+        return Ok(());
+    }
+    if declared {
+        return Err(PzxError::runtime(target.span(), format!("`{}` is already declared", name)));
+    }
+    if constant {
+        state.constants.last_mut().unwrap().insert(name.clone());
+    }
+    // Unlike other assignments, a declaration hides a global of the same name
+    match state.frames.last_mut() {
+        Some(frame) => frame.insert(name, value),
+        None => state.variables.insert(name, value),
+    };
+    Ok(())
+}
+
+// Constants and built-in variables cannot be assigned, and neither can their fields
+fn writable(state: &Interpreter, target: &Operand) -> Result<(), PzxError> {
+    let name = variable_name(target);
+    let base = name.split('.').next().unwrap_or_default();
+    let local = state.frames.last().is_some_and(|frame| frame.contains_key(base));
+    match check::built_in(base) {
+        Some(changed) if !local => Err(PzxError::runtime(target.span(), format!("`{}` is built in and {}", base, changed))),
+        _ if state.constant(base) => Err(PzxError::runtime(target.span(), format!("`{}` is a constant", base))),
+        _ => Ok(()),
+    }
+}
+
+// Where a statement puts what it produced. A variable keeps the type it was given, except for
+// `out`, and a field, as in `AWAIT form.name`, is added to a map that has to exist already.
+fn store(state: &mut Interpreter, target: &Operand, value: Variable) -> Result<(), PzxError> {
+    let name = variable_name(target);
+    match state.lookup(&name) {
+        Some(old) if name != "out" && !name.contains('.') && std::mem::discriminant(old) != std::mem::discriminant(&value) => {
+            let message = format!("`{}` holds {}, which cannot be replaced by {}", name, old.type_name(), value.type_name());
+            Err(PzxError::runtime(target.span(), message))
+        },
+        _ => put(state, target, value),
+    }
+}
+
+// Stores a value whatever the type of the variable it replaces
+fn put(state: &mut Interpreter, target: &Operand, value: Variable) -> Result<(), PzxError> {
+    writable(state, target)?;
+    let name = variable_name(target);
+    let (parent, field) = match name.rfind('.') {
+        Some(dot) => (&name[..dot], &name[dot + 1..]),
+        None => {
+            state.assign(name, value);
+            return Ok(());
+        },
+    };
+
+    match state.lookup_mut(parent) {
+        Some(Variable::Map(fields)) => {
+            fields.insert(field.to_owned(), value);
+            Ok(())
+        },
+        Some(_) => Err(PzxError::runtime(target.span(), format!("`{}` is not a map, so `{}` cannot be set", parent, name))),
+        None => Err(undefined(parent, target.span())),
+    }
+}
+
+// The parser only lets variables through where a name is expected
+fn variable_name(operand: &Operand) -> String {
+    match operand {
+        Operand::Identifier(name, _) => name.to_owned(),
+        Operand::Literal(s, ..) => s.to_owned(),
+        _ => operand.to_string(),
+    }
+}
+
+// Procedures the script defined come before functions the host registered
+fn call(state: &mut Interpreter, name: &str, arguments: &[Variable], span: Span) -> Result<Variable, PzxError> {
+    if let Some(procedure) = state.procedures.get(name).cloned() {
+        return call_procedure(state, name, &procedure, arguments, span);
+    }
+
+    // The function is taken out of the state while it runs, so that it can borrow nothing else
+    let mut function = match state.functions.remove(name) {
+        Some(function) => function,
+        None => return Err(PzxError::runtime(span, format!("Unknown function `{}`", name))),
+    };
+    let result = function(arguments);
+    state.functions.insert(name.to_owned(), function);
+    result.map_err(|e| PzxError::runtime(span, format!("{}: {}", name, e)))
+}
+
+// A procedure's parameters are its first local variables. Without a RETURN it gives back an
+// empty string.
+fn call_procedure(state: &mut Interpreter, name: &str, procedure: &Procedure, arguments: &[Variable], span: Span) -> Result<Variable, PzxError> {
+    if procedure.parameters.len() != arguments.len() {
+        return Err(PzxError::runtime(span, format!("`{}` takes {} arguments but was given {}", name, procedure.parameters.len(), arguments.len())));
+    }
+    if state.frames.len() >= MAX_DEPTH {
+        return Err(PzxError::runtime(span, format!("Procedures nested more than {} deep; `{}` may be recursing without end", MAX_DEPTH, name)));
+    }
+
+    let frame = procedure.parameters.iter().cloned().zip(arguments.iter().cloned()).collect();
+    state.frames.push(frame);
+    state.constants.push(HashSet::new());
+    let result = execute(&procedure.body, state);
+    state.frames.pop();
+    state.constants.pop();
+
+    let value = state.returning.take().unwrap_or_else(|| Variable::Str(String::new()));
+    result.map(|_| value)
+}
+
+// The value of any operand. A quoted literal is a string, even if it reads as a number, and one
+// without quotes is a number.
+fn evaluate(state: &mut Interpreter, operand: &Operand) -> Result<Variable, PzxError> {
+    match operand {
+        Operand::Literal(s, true, _) => Ok(Variable::Str(s.to_owned())),
+        Operand::Literal(s, false, span) => s.parse().map(Variable::Num).map_err(|_| PzxError::runtime(*span, format!("`{}` is not a number", s))),
+        Operand::Identifier(var, span) => state.lookup(var).cloned().ok_or_else(|| undefined(var, *span)),
+        Operand::Call(function, span) => call(state, function, &[], *span),
+        Operand::List(items, _) => {
+            let mut res = Vec::new();
+            for item in items.iter() {
+                res.push(evaluate(state, item)?);
+            }
+            Ok(Variable::List(res))
+        },
+        Operand::Bool(value, _) => Ok(Variable::Bool(*value)),
+        Operand::Compare(left, comparison, right, span) => {
+            let left = evaluate(state, left)?;
+            let right = evaluate(state, right)?;
+            compare(&left, *comparison, &right).map(Variable::Bool).map_err(|e| PzxError::runtime(*span, e))
+        },
+        Operand::Map(fields, _) => {
+            let mut res = BTreeMap::new();
+            for (key, value) in fields.iter() {
+                res.insert(key.to_owned(), evaluate(state, value)?);
+            }
+            Ok(Variable::Map(res))
+        },
+        Operand::Index(list, index, _) => {
+            let items = match evaluate(state, list)? {
+                Variable::List(items) => items,
+                _ => return Err(PzxError::runtime(list.span(), format!("`{}` is not a list, so it cannot be indexed", list))),
+            };
+            let position = match evaluate(state, index)? {
+                Variable::Num(i) => i,
+                _ => return Err(PzxError::runtime(index.span(), format!("Index `{}` is not a number", index))),
+            };
+            // Negative indexes count from the end, as in `xs[-1]`
+            let actual = if position < 0 { items.len() as i32 + position } else { position };
+            if actual < 0 || actual as usize >= items.len() {
+                return Err(PzxError::runtime(index.span(), format!("Index {} is out of bounds for a list of {} items", position, items.len())));
+            }
+            Ok(items[actual as usize].clone())
+        },
+    }
+}
+
+// Any two values can be checked for equality, but only numbers with numbers and strings with
+// strings can be ordered
+fn compare(left: &Variable, comparison: Comparison, right: &Variable) -> Result<bool, String> {
+    let ordering = match (left, right) {
+        (Variable::Num(a), Variable::Num(b)) => Some(a.cmp(b)),
+        (Variable::Str(a), Variable::Str(b)) => Some(a.cmp(b)),
+        _ => None,
+    };
+
+    match (comparison, ordering) {
+        (Comparison::Equal, _) => Ok(left == right),
+        (Comparison::NotEqual, _) => Ok(left != right),
+        (Comparison::Less, Some(ordering)) => Ok(ordering.is_lt()),
+        (Comparison::LessOrEqual, Some(ordering)) => Ok(ordering.is_le()),
+        (Comparison::Greater, Some(ordering)) => Ok(ordering.is_gt()),
+        (Comparison::GreaterOrEqual, Some(ordering)) => Ok(ordering.is_ge()),
+        (_, None) => Err(format!("Cannot order {} and {} with {}", left, right, comparison.symbol())),
+    }
+}
+
+// The statements that run other programs, kept apart so that the frames of the recursion
+// through execute_statement stay small
+fn execute_command(statement: &Statement, state: &mut Interpreter) -> Result<(), PzxError> {
+    let name = statement.operator.name();
+    match &statement.kind {
+        // The command cannot read the keyboard, and what it prints is kept rather than shown
+        StatementKind::Exec(target, command) => {
+            let mut command = command_value(state, command, name)?;
+            command.stdin(Stdio::null());
+            let output = command.output().map_err(|e| could_not_run(statement.span, e))?;
+            let mut result = BTreeMap::new();
+            result.insert("stdout".to_owned(), Variable::Str(String::from_utf8_lossy(&output.stdout).trim_end_matches('\n').to_owned()));
+            result.insert("stderr".to_owned(), Variable::Str(String::from_utf8_lossy(&output.stderr).trim_end_matches('\n').to_owned()));
+            result.insert("status".to_owned(), Variable::Num(output.status.code().unwrap_or(-1)));
+            store(state, target, Variable::Map(result))?;
+        },
+        // The command gets the terminal to itself, and the screen is drawn again once it ends
+        StatementKind::Shell(target, command) => {
+            let mut command = command_value(state, command, name)?;
+            state.backend.suspend();
+            let status = command.status();
+            state.backend.resume();
+            let status = status.map_err(|e| could_not_run(statement.span, e))?;
+            store(state, target, Variable::Num(status.code().unwrap_or(-1)))?;
+        },
+        // The command runs with nothing to read and its output thrown away, while the spinner
+        // turns at the cursor. It is erased once the command ends.
+        StatementKind::Spin(label, command, target) => {
+            let label = text(state, label, name)?;
+            let mut command = command_value(state, command, name)?;
+            command.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null());
+            let mut child = command.spawn().map_err(|e| could_not_run(statement.span, e))?;
+
+            let at = state.backend.cursor();
+            let mut frame = 0;
+            let status = loop {
+                progress::spinner(state.backend, at, frame, &label);
+                if let Some(status) = child.try_wait().map_err(|e| could_not_run(statement.span, e))? {
+                    break status;
+                }
+                thread::sleep(Duration::from_millis(progress::FRAME_MS));
+                frame += 1;
+            };
+            progress::clear(state.backend, at, label.chars().count() + 2);
+            if let Some(target) = target {
+                store(state, target, Variable::Num(status.code().unwrap_or(-1)))?;
+            }
+        },
+        // The bar is drawn at the cursor, which stays where it is so the next GAUGE draws over it
+        StatementKind::Gauge(label, GaugeSource::Value(value)) => {
+            let label = text(state, label, name)?;
+            let percent = number(state, value, name)?;
+            let at = state.backend.cursor();
+            progress::gauge(state.backend, at, &label, percent);
+        },
+        StatementKind::Gauge(label, GaugeSource::Exec(command, target)) => {
+            let label = text(state, label, name)?;
+            let mut command = command_value(state, command, name)?;
+            command.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::null());
+            let mut child = command.spawn().map_err(|e| could_not_run(statement.span, e))?;
+
+            // The lines are read on a thread of their own, so that the bar is drawn as soon as
+            // each one arrives
+            let stdout = child.stdout.take().expect("Err: Could not read the command's output");
+            let (sender, receiver) = mpsc::channel();
+            thread::spawn(move || {
+                for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                    if sender.send(line).is_err() {
+                        break;
+                    }
+                }
+            });
+
+            let at = state.backend.cursor();
+            let mut input = GaugeInput::default();
+            progress::gauge(state.backend, at, &label, 0);
+            for line in receiver {
+                if input.line(&line) {
+                    progress::gauge(state.backend, at, input.label.as_deref().unwrap_or(&label), input.percent);
+                }
+            }
+            let status = child.wait().map_err(|e| could_not_run(statement.span, e))?;
+            if let Some(target) = target {
+                store(state, target, Variable::Num(status.code().unwrap_or(-1)))?;
+            }
+        },
+        StatementKind::Tail(target, source) => {
+            let lines = tail(state, source, name)?;
+            store(state, target, Variable::List(lines.into_iter().map(Variable::Str).collect()))?;
+        },
+        StatementKind::Pager(operand, file) => {
+            let text = match (text(state, operand, name)?, file) {
+                (path, true) => std::fs::read_to_string(&path).map_err(|e| PzxError::runtime(operand.span(), format!("Could not read {}: {}", path, e)))?,
+                (text, false) => text,
+            };
+            page(state, &text)?;
+        },
+        StatementKind::Form(fields, button) => form(state, fields, button.as_ref(), name)?,
+        _ => {},
+    }
+
+    Ok(())
+}
+
+// Shows text from the line below the cursor down until the user presses q. Without a keyboard
+// only the first page is shown.
+fn page(state: &mut Interpreter, text: &str) -> Result<(), PzxError> {
+    let (y, x) = state.backend.cursor();
+    let top = if x == 0 { y } else { y + 1 };
+    let mut pager = Pager::new(text, top.min(state.backend.size().0 - 2).max(0));
+    loop {
+        pager.render(state.backend);
+        match read_key(state) {
+            Key::Eof => break,
+            Key::Resize => handle_resize(state)?,
+            key => {
+                if pager.key(key, state.backend) {
+                    break;
+                }
+            },
+        }
+    }
+
+    // The status line is blanked for whatever the script prints next
+    let status = pager.height(state.backend) as i32 + top;
+    let width = state.backend.size().1 as usize;
+    state.backend.set_cursor_visible(false);
+    state.backend.move_cursor(status, 0);
+    state.backend.print(&" ".repeat(width.saturating_sub(1)));
+    state.backend.move_cursor(status, 0);
+    sync_cursor(state);
+    Ok(())
+}
+
+// Shows the fields from the line below the cursor down until they are submitted, then stores
+// what was entered. A field starts out with the value its variable already holds, so a form
+// shown again can be corrected. Without a keyboard the values are stored as they are.
+fn form(state: &mut Interpreter, fields: &[Field], button: Option<&Button>, operator: &str) -> Result<(), PzxError> {
+    let (y, x) = state.backend.cursor();
+    let top = if x == 0 { y } else { y + 1 };
+    let mut form = Form::new(top.min(state.backend.size().0 - 1).max(0));
+    for field in fields {
+        let label = text(state, &field.label, operator)?;
+        let input = match &field.kind {
+            FieldKind::Text => Input::Text,
+            FieldKind::Number => Input::Number,
+            FieldKind::Password => Input::Password,
+            FieldKind::Choice(options) => match evaluate(state, options)? {
+                Variable::List(items) if items.is_empty() => {
+                    return Err(PzxError::runtime(options.span(), format!("`{}` has no options to choose from", options)));
+                },
+                Variable::List(items) => Input::Choice(items.into_iter().map(|item| match item {
+                    Variable::Str(s) => s,
+                    other => other.to_string(),
+                }).collect()),
+                _ => return Err(improper(options, operator)),
+            },
+        };
+        let value = match state.lookup(&variable_name(&field.target)) {
+            Some(Variable::Str(s)) => s.clone(),
+            Some(Variable::Num(n)) => n.to_string(),
+            _ => String::new(),
+        };
+        let at = position(state, &field.at, operator)?;
+        form.add(&label, input, value, at);
+    }
+    if let Some(button) = button {
+        let label = text(state, &button.label, operator)?;
+        let at = position(state, &button.at, operator)?;
+        form.button(&label, at);
+    }
+
+    loop {
+        form.render(state.backend);
+        match read_key(state) {
+            Key::Eof => break,
+            Key::Resize => handle_resize(state)?,
+            key => {
+                if form.key(key) {
+                    break;
+                }
+            },
+        }
+    }
+
+    for (field, entry) in fields.iter().zip(&form.entries) {
+        let value = match entry.input {
+            Input::Number => Variable::Num(entry.value.parse().unwrap_or(0)),
+            _ => Variable::Str(entry.value.clone()),
+        };
+        store(state, &field.target, value)?;
+    }
+
+    // The line below the form may still hold a complaint about a number
+    let (lines, cols) = state.backend.size();
+    let bottom = form.bottom().min(lines - 1);
+    state.backend.set_cursor_visible(false);
+    state.backend.move_cursor(bottom, 0);
+    state.backend.print(&" ".repeat((cols - 1).max(0) as usize));
+    state.backend.move_cursor(bottom, 0);
+    sync_cursor(state);
+    Ok(())
+}
+
+fn position(state: &mut Interpreter, at: &Option<(Operand, Operand)>, operator: &str) -> Result<Option<(i32, i32)>, PzxError> {
+    match at {
+        Some((line, column)) => Ok(Some((number(state, line, operator)?, number(state, column, operator)?))),
+        None => Ok(None),
+    }
+}
+
+// Shows lines as they arrive from the line below the cursor down, until the user presses q.
+// Without a keyboard it ends once a file has been read or a command has ended. Returns the lines
+// kept for scrolling back.
+fn tail(state: &mut Interpreter, source: &TailSource, operator: &str) -> Result<Vec<String>, PzxError> {
+    // Lines come from threads of their own, and None says everything so far has been read
+    let (sender, receiver) = mpsc::channel();
+    let mut child = None;
+    match source {
+        TailSource::Exec(command) => {
+            let span = command.span();
+            let mut command = command_value(state, command, operator)?;
+            command.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped());
+            let mut spawned = command.spawn().map_err(|e| could_not_run(span, e))?;
+            let stdout = spawned.stdout.take().expect("Err: Could not read the command's output");
+            let stderr = spawned.stderr.take().expect("Err: Could not read the command's output");
+            send_lines(stdout, sender.clone(), false);
+            send_lines(stderr, sender, false);
+            child = Some(spawned);
+        },
+        TailSource::File(path) => {
+            let name = text(state, path, operator)?;
+            let file = File::open(&name).map_err(|e| PzxError::runtime(path.span(), format!("Could not read {}: {}", name, e)))?;
+            send_lines(file, sender, true);
+        },
+    }
+
+    let (y, x) = state.backend.cursor();
+    let top = if x == 0 { y } else { y + 1 };
+    let mut view = Tail::new(top.min(state.backend.size().0 - 2).max(0));
+    let mut keyboard = true;
+    loop {
+        // Everything that has arrived is taken in; without a keyboard there is time to wait
+        let mut read = false;
+        loop {
+            let message = if keyboard {
+                receiver.try_recv().map_err(|e| e == TryRecvError::Disconnected)
+            } else {
+                receiver.recv_timeout(Duration::from_millis(progress::FRAME_MS)).map_err(|e| e == RecvTimeoutError::Disconnected)
+            };
+            match message {
+                Ok(Some(line)) => view.push(line),
+                Ok(None) | Err(true) => {
+                    read = true;
+                    break;
+                },
+                Err(false) => break,
+            }
+        }
+        view.render(state.backend);
+        if !keyboard {
+            if read {
+                break;
+            }
+            continue;
+        }
+
+        match poll_key(state, progress::FRAME_MS as i32) {
+            None => {},
+            Some(Key::Eof) => keyboard = false,
+            Some(Key::Resize) => {
+                handle_resize(state)?;
+                view.redraw();
+            },
+            Some(key) => {
+                let page = view.height(state.backend);
+                if view.key(key, page) {
+                    break;
+                }
+            },
+        }
+    }
+
+    if let Some(mut child) = child {
+        child.kill().ok();
+        child.wait().ok();
+    }
+    state.backend.set_scroll_region(None);
+    state.backend.set_cursor_visible(false);
+    state.backend.move_cursor(view.height(state.backend) as i32 + top, 0);
+    sync_cursor(state);
+    Ok(view.lines.into_iter().collect())
+}
+
+// Sends every line read to TAIL. A file is followed as it grows, saying each time it has been
+// read to the end; the thread ends once TAIL stops listening.
+fn send_lines<R: Read + Send + 'static>(source: R, sender: Sender<Option<String>>, follow: bool) {
+    thread::spawn(move || {
+        let mut reader = BufReader::new(source);
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match reader.read_line(&mut line) {
+                Ok(0) | Err(_) if !follow => break,
+                Ok(0) | Err(_) => {
+                    if sender.send(None).is_err() {
+                        break;
+                    }
+                    thread::sleep(Duration::from_millis(progress::FRAME_MS));
+                },
+                Ok(_) => {
+                    if sender.send(Some(line.trim_end_matches(&['\n', '\r'][..]).to_owned())).is_err() {
+                        break;
+                    }
+                },
+            }
+        }
+    });
+}
+
+fn could_not_run(span: Span, e: io::Error) -> PzxError {
+    PzxError::runtime(span, format!("Could not run the command: {}", e))
+}
+
+// A string runs in the shell, while a list names the program and its arguments, which reach it
+// without being split or expanded
+fn command_value(state: &mut Interpreter, operand: &Operand, operator: &str) -> Result<Command, PzxError> {
+    match evaluate(state, operand)? {
+        Variable::Str(line) => {
+            let mut res = Command::new("sh");
+            res.arg("-c").arg(line);
+            Ok(res)
+        },
+        Variable::List(items) if !items.is_empty() => {
+            let words: Vec<String> = items.iter().map(|item| match item {
+                Variable::Str(s) => s.to_owned(),
+                other => other.to_string(),
+            }).collect();
+            let mut res = Command::new(&words[0]);
+            res.args(&words[1..]);
+            Ok(res)
+        },
+        _ => Err(improper(operand, operator)),
+    }
+}
+
+// STRING and NUM change a variable but cannot create one
+fn declared(state: &Interpreter, target: &Operand, operator: &str) -> Result<(), PzxError> {
+    match state.lookup(&variable_name(target)) {
+        Some(_) => Ok(()),
+        None => Err(improper(target, operator)),
+    }
+}
+
+fn text(state: &mut Interpreter, operand: &Operand, operator: &str) -> Result<String, PzxError> {
+    match operand {
+        Operand::Literal(s, ..) => Ok(s.to_owned()),
+        Operand::Identifier(var, span) => match state.lookup(var) {
+            Some(Variable::Str(s)) => Ok(s.to_owned()),
+            Some(_) => Err(improper(operand, operator)),
+            // Scripts from before variables could leave text after STRING unquoted
+            None => Err(PzxError::runtime(*span, format!("`{}` is not defined; text has to be quoted, as in \"{}\"", var, var))),
+        },
+        _ => match evaluate(state, operand)? {
+            Variable::Str(s) => Ok(s),
+            _ => Err(improper(operand, operator)),
+        },
+    }
+}
+
+fn number(state: &mut Interpreter, operand: &Operand, operator: &str) -> Result<i32, PzxError> {
+    match operand {
+        Operand::Literal(s, false, _) => {
+            s.parse().map_err(|_| improper(operand, operator))
+        },
+        Operand::Identifier(var, _) => {
+            if let Some(Variable::Num(i)) = state.lookup(var) {
+                Ok(*i)
+            } else {
+                Err(improper(operand, operator))
+            }
+        },
+        _ => match evaluate(state, operand)? {
+            Variable::Num(i) => Ok(i),
+            _ => Err(improper(operand, operator)),
+        },
+    }
+}
+
+fn component(operand: &Operand, operator: &str) -> Result<i16, PzxError> {
+    variable_name(operand).parse::<i16>()
+        .map_err(|_| PzxError::runtime(operand.span(), format!("Color literal provided to {} operator not composed of digits", operator)))
+}
+
+fn color_value(state: &Interpreter, color: &ColorOperand, operator: &str) -> Result<Variable, PzxError> {
+    match color {
+        ColorOperand::Variable(var) => {
+            match state.lookup(&variable_name(var)) {
+                Some(color @ Variable::Clr{..}) => Ok(color.clone()),
+                _ => Err(improper(var, operator)),
+            }
+        },
+        ColorOperand::Rgb(r, g, b) => Ok(Variable::Clr {
+            r: component(r, operator)?,
+            g: component(g, operator)?,
+            b: component(b, operator)?,
+        }),
+    }
+}
+
+// Reads a line of input the same way getstr would, but keeps reacting to terminal resizes
+fn read_line(state: &mut Interpreter) -> Result<String, PzxError> {
+    state.backend.set_cursor_visible(true);
+
+    let mut buffer = String::new();
+    loop {
+        match read_key(state) {
+            Key::Resize => {
+                handle_resize(state)?;
+                // The handler may have redrawn the screen, so show the pending input again
+                state.backend.set_cursor_visible(true);
+                state.backend.print(&buffer);
+            },
+            Key::Enter | Key::Eof => {
+                break;
+            },
+            Key::Backspace => {
+                let (y, x) = state.backend.cursor();
+                if buffer.pop().is_some() {
+                    state.backend.move_cursor(y, x - 1);
+                    state.backend.delete_char();
+                }
+            },
+            Key::Char(c) => {
+                buffer.push(c);
+                state.backend.print(&c.to_string());
+            },
+            _ => {},
+        }
+    }
+
+    state.backend.set_cursor_visible(false);
+    Ok(buffer)
+}
+
+// Replayed keys take the place of the keyboard entirely when they were provided
+fn read_key(state: &mut Interpreter) -> Key {
+    let key = match &mut state.replay {
+        Some(replay) => {
+            // Nothing else refreshes the screen when the keyboard is never read
+            state.backend.refresh();
+            replay.next_key()
+        },
+        None => state.backend.read_key(),
+    };
+
+    if let Some(trace) = &state.trace {
+        trace.debug(&format!("key {:?}", key));
+    }
+    key
+}
+
+// A key if one comes within the timeout. Replayed keys are always there, until they run out.
+fn poll_key(state: &mut Interpreter, timeout: i32) -> Option<Key> {
+    let key = match &mut state.replay {
+        Some(replay) => {
+            state.backend.refresh();
+            Some(replay.next_key())
+        },
+        None => state.backend.poll_key(timeout),
+    };
+
+    if let (Some(trace), Some(key)) = (&state.trace, key) {
+        trace.debug(&format!("key {:?}", key));
+    }
+    key
+}
+
+// Output and input move the real cursor, so x and y are read back from the backend
+fn sync_cursor(state: &mut Interpreter) {
+    let (y, x) = state.backend.cursor();
+    state.variables.insert("x".to_owned(), Variable::Num(x));
+    state.variables.insert("y".to_owned(), Variable::Num(y));
+}
+
+// The backend has already resized its screen by the time Key::Resize is read
+pub(crate) fn handle_resize(state: &mut Interpreter) -> Result<(), PzxError> {
+    let (lines, cols) = state.backend.size();
+    state.variables.insert("G".to_owned(), Variable::Num(lines-1));
+    state.variables.insert("$".to_owned(), Variable::Num(cols-1));
+
+    if let Some(trace) = &state.trace {
+        trace.info(&format!("resize {}x{} -> G={} $={}", lines, cols, lines-1, cols-1));
+    }
+
+    if let Some(handler) = state.handlers.get("RESIZE").cloned() {
+        execute(&handler, state)?;
+    }
+
+    Ok(())
+}
+
+pub(crate) fn change_foreground(backend: &mut dyn Backend, color: &Variable) -> Result<(), ()> {
+    if let Variable::Clr{r, g, b} = color {
+        backend.define_color(16, *r, *g, *b);
+    } else {
+        return Err(());
+    }
+
+    Ok(())
+}
+
+pub(crate) fn change_background(backend: &mut dyn Backend, color: &Variable) -> Result<(), ()> {
+    if let Variable::Clr{r, g, b} = color {
+        backend.define_color(17, *r, *g, *b);
+    } else {
+        return Err(());
+    }
+
+    Ok(())
+}
+
+fn update_color_pairs(backend: &mut dyn Backend, foreground: &Variable, background: &Variable) {
+    change_foreground(backend, foreground).expect("Could not change foreground color");
+    change_background(backend, background).expect("Could not change background color");
+
+    backend.define_pair(1, 16, 17);
+    backend.use_pair(1);
+    backend.clear();
+}
+
+fn global_variables(backend: &mut dyn Backend) -> HashMap<String, Variable> {
+    let (lines, cols) = backend.size();
+    let mut res: HashMap<String, Variable> = HashMap::new();
+    // Cursor position
+    res.insert("x".to_owned(), Variable::Num(0));
+    res.insert("y".to_owned(), Variable::Num(0));
+    // Screen-relative positions
+    res.insert("G".to_owned(), Variable::Num(lines-1));
+    res.insert("$".to_owned(), Variable::Num(cols-1));
+    // Colors
+    res.insert("foreground".to_owned(), Variable::Clr{r: 1000, g: 1000, b: 1000});
+    res.insert("background".to_owned(), Variable::Clr{r: 0, g: 0, b: 0});
+    update_color_pairs(backend, res.get("foreground").unwrap(), res.get("background").unwrap());
+    // Standard output
+    res.insert("out".to_owned(), Variable::Str(String::new()));
+    // Arguments from the command line
+    res.insert("args".to_owned(), Variable::List(Vec::new()));
+    res
+}
+// What a test script left: the screen, the state after the first script and what each later one reported
+#[cfg(test)]
+struct Run {
+    screen: crate::backend::VirtualScreen,
+    outcome: Outcome,
+    errors: Vec<String>,
+    variables: HashMap<String, Variable>, // Once every script has run
+}
+
+// Runs the first script on the screen and then the others against the state it left, each expected to fail
+#[cfg(test)]
+fn run_script(screen: crate::backend::VirtualScreen, scripts: &[&str]) -> Run {
+    run_prepared(screen, |_| {}, scripts)
+}
+
+// The same, for tests that give the interpreter arguments, variables or functions first
+#[cfg(test)]
+fn run_prepared(mut screen: crate::backend::VirtualScreen, setup: impl FnOnce(&mut Interpreter), scripts: &[&str]) -> Run {
+    let mut interpreter = Interpreter::new(&mut screen);
+    setup(&mut interpreter);
+    let outcome = interpreter.run(&Program::parse(scripts[0]).unwrap()).unwrap();
+    let errors = scripts[1..].iter()
+        .map(|script| interpreter.run(&Program::parse(script).unwrap()).unwrap_err().to_string()).collect();
+    let variables = interpreter.variables.clone();
+    drop(interpreter);
+    Run { screen, outcome, errors, variables }
+}
+
+#[test]
+fn cursor_test() {
+    let run = run_script(crate::backend::VirtualScreen::new(24, 80), &["MOVE 1 2; PRINT \"hi\"; SHIFT 1 0; PRINT \"!\"; SAVEPOS a; MOVE 0 0; RESTOREPOS a; PRINT \"?\""]);
+    assert_eq!(run.screen.row(1), "  hi");
+    assert_eq!(run.screen.row(2), "    !?");
+}
+
+#[test]
+fn input_test() {
+    let mut screen = crate::backend::VirtualScreen::new(24, 80);
+    screen.push_str("y");
+    screen.push_str("Jom");
+    screen.push_key(Key::Backspace);
+    screen.push_str("hn\n");
+    let run = run_script(screen, &["ACCEPT a; PRINT \" Name: \"; AWAIT out"]);
+    assert_eq!(run.outcome.out, "John");
+    assert_eq!(run.screen.row(0), "y Name: John");
+}
+
+#[test]
+fn resize_test() {
+    let mut screen = crate::backend::VirtualScreen::new(24, 80);
+    screen.resize(10, 40);
+    screen.push_str("ok\n");
+    let run = run_script(screen, &["ON RESIZE; MOVE G 0; PRINT \"resized\"; END; AWAIT out"]);
+    assert_eq!(run.outcome.out, "ok");
+    assert_eq!(run.screen.row(9), "resizedok");
+}
+
+#[test]
+fn color_test() {
+    let run = run_script(crate::backend::VirtualScreen::new(24, 80), &["CLR orange 900 400 400; CLR blue 0 0 1000; BACK orange; FORE blue"]);
+    assert_eq!(run.screen.cell_colors(5, 5), ((0, 0, 1000), (900, 400, 400)));
+}
+
+#[test]
+fn snapshot_test() {
+    let path = std::env::temp_dir().join(format!("pzx-snapshot-test-{}.txt", std::process::id()));
+    run_script(crate::backend::VirtualScreen::new(24, 80), &[&format!("MOVE 1 0; PRINT \"saved\"; SNAPSHOT \"{}\" FORMAT text; PRINT \" later\"", path.display())]);
+    let saved = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(saved, "\nsaved\n");
+}
+
+#[test]
+fn interpreter_test() {
+    // Variables are kept for the next script, and failures say where they happened. Text has to be
+    // quoted, unlike in the first versions.
+    let run = run_prepared(crate::backend::VirtualScreen::new(24, 80), |interpreter| interpreter.set_var("name", Variable::Str("Jon".to_owned())),
+        &["LET n = 3; STRING out name; PRINT name", "MOVE 0 0;
+MOVE name 1", "STRING out hi"]);
+    assert_eq!(run.outcome.out, "Jon");
+    assert_eq!(run.outcome.variables.get("n"), Some(&Variable::Num(3)));
+    assert_eq!(run.errors, vec![
+        "2:6: error: Improper operand provided to MOVE operator",
+        "1:12: error: `hi` is not defined; text has to be quoted, as in \"hi\"",
+    ]);
+    assert_eq!(run.variables.get("x"), Some(&Variable::Num(0)));
+}
+
+#[test]
+fn function_test() {
+    let run = run_prepared(crate::backend::VirtualScreen::new(24, 80), |interpreter| {
+        let mut calls = 0;
+        interpreter.register_fn("branches", move |arguments| {
+            calls += 1;
+            match arguments {
+                [Variable::Str(remote), Variable::Num(count)] => Ok(Variable::Str(format!("{} {} {}", remote, count, calls))),
+                _ => Err("expects a remote and a count".to_owned()),
+            }
+        });
+    }, &["CALL branches \"origin\" 2 INTO out; CALL branches \"origin\" y INTO b; PRINT version()", "CALL branches 1", "STRING out missing()"]);
+    assert_eq!(run.outcome.out, "origin 2 1");
+    assert_eq!(run.outcome.variables.get("b"), Some(&Variable::Str("origin 0 2".to_owned())));
+    assert_eq!(run.errors, vec![
+        "1:6: error: branches: expects a remote and a count",
+        "1:12: error: Unknown function `missing`",
+    ]);
+    assert_eq!(run.screen.row(0), env!("CARGO_PKG_VERSION"));
+}
+
+#[test]
+fn procedure_test() {
+    let run = run_script(crate::backend::VirtualScreen::new(24, 80), &["STRING out \"\"; CLR title 0 0 0;
+PROC header title; MOVE 0 0; PRINT title; STRING out title; CLR local 1 1 1; END;
+PROC twice n; LET m = n; SHIFT 0 n; RETURN x; PRINT \"never\"; END;
+CALL header \"Step 2\"; CALL twice 3 INTO moved", "PROC loop; CALL loop; END; CALL loop", "CALL header"]);
+    // Parameters shadow globals of the same name, and new variables are local to the call
+    assert_eq!(run.outcome.out, "Step 2");
+    assert_eq!(run.outcome.variables.get("title"), Some(&Variable::Clr{r: 0, g: 0, b: 0}));
+    assert_eq!(run.outcome.variables.get("local"), None);
+    assert_eq!(run.outcome.variables.get("moved"), Some(&Variable::Num(9)));
+    assert_eq!(run.errors, vec![
+        format!("1:17: error: Procedures nested more than {} deep; `loop` may be recursing without end", MAX_DEPTH),
+        "1:6: error: `header` takes 1 arguments but was given 0".to_owned(),
+    ]);
+    assert_eq!(run.screen.row(0).trim_end(), "Step 2");
+}
+
+#[test]
+fn list_test() {
+    let run = run_script(crate::backend::VirtualScreen::new(24, 80), &["STRING out \"\";
+LIST xs [\"a\", \"b\",x]; PUSH xs \"c\"; POP xs last; PUSH xs [1, 2];
+FOR item IN xs; PRINT item; END; PRINT xs[-1][0]; LET second = xs[3][1];
+CALL split \"one,two\" \",\" INTO parts; CALL join parts \" & \" INTO out; CALL len xs INTO n", "PRINT xs[4]"]);
+    assert_eq!(run.outcome.variables.get("last"), Some(&Variable::Str("c".to_owned())));
+    assert_eq!(run.outcome.variables.get("xs").unwrap().to_string(), "[\"a\", \"b\", 0, [1, 2]]");
+    assert_eq!(run.outcome.variables.get("n"), Some(&Variable::Num(4)));
+    assert_eq!(run.outcome.out, "one & two");
+    assert_eq!(run.errors, vec!["1:10: error: Index 4 is out of bounds for a list of 4 items"]);
+    // Only strings are printed
+    assert_eq!(run.screen.row(0).trim_end(), "ab");
+}
+
+#[test]
+fn map_test() {
+    let mut screen = crate::backend::VirtualScreen::new(24, 80);
+    screen.push_str("db\n");
+    let run = run_script(screen, &["MAP cfg { port: 8080, name: \"x\", tags: [\"a\"] };
+AWAIT cfg.name; NUM cfg.port 9090; MAP cfg.extra {}; FOR key IN cfg; PRINT key; END;
+LET port = cfg.port; MAP out { form: cfg, \"size\": 2 }", "AWAIT cfg.port.y"]);
+    assert_eq!(run.outcome.variables.get("port"), Some(&Variable::Num(9090)));
+    assert_eq!(run.outcome.out, "{\"form\":{\"extra\":{},\"name\":\"db\",\"port\":9090,\"tags\":[\"a\"]},\"size\":2}");
+    assert_eq!(run.errors, vec!["1:7: error: `cfg.port` is not a map, so `cfg.port.y` cannot be set"]);
+    assert_eq!(run.screen.row(0).trim_end(), "dbextranameporttags");
+}
+
+#[test]
+fn bool_test() {
+    let mut screen = crate::backend::VirtualScreen::new(24, 80);
+    screen.push_keys(vec![Key::Char('x'), Key::Char('Y'), Key::Enter]);
+    let run = run_script(screen, &["CONFIRM sure; CONFIRM again; LIST xs []; STRING out \"\";
+BOOL empty xs; BOOL small x < 10; BOOL same \"a\" == \"b\"; BOOL named out;
+IF sure; PRINT \" yes\"; ELSE; PRINT \" no\"; END; IF again == FALSE; PRINT \" not again\"; END", "IF \"a\" < 1; END"]);
+    let bools: Vec<bool> = ["sure", "again", "empty", "small", "same", "named"].iter()
+        .map(|name| run.outcome.variables.get(*name) == Some(&Variable::Bool(true))).collect();
+    assert_eq!(bools, vec![true, false, false, true, false, false]);
+    assert_eq!(run.errors, vec!["1:4: error: Cannot order \"a\" and 1 with <"]);
+    assert_eq!(run.screen.row(0).trim_end(), "yn yes not again");
+
+    // A quoted number is a string, so it equals what was typed, while a number does not
+    let mut screen = crate::backend::VirtualScreen::new(24, 80);
+    screen.push_keys(vec![Key::Char('1'), Key::Enter]);
+    let run = run_script(screen, &["AWAIT a; BOOL text a == \"1\"; BOOL number a == 1"]);
+    assert_eq!((run.outcome.variables.get("text"), run.outcome.variables.get("number")), (Some(&Variable::Bool(true)), Some(&Variable::Bool(false))));
+}
+
+#[test]
+fn declaration_test() {
+    let run = run_script(crate::backend::VirtualScreen::new(24, 80), &["CONST width = 40; LET count = 0; SET count = width; LET names = [\"a\"];
+PROC local; LET count = \"shadowed\"; CONST width = 1; SET width = 2; END",
+        "LET count = 1", "SET width = 1", "SET count = \"a\"", "SET size = 1", "NUM x 3", "PUSH G 1", "CLR foreground 0 0 0", "CALL local"]);
+    assert_eq!(run.outcome.variables.get("count"), Some(&Variable::Num(40)));
+    assert_eq!(run.errors, vec![
+        "1:5: error: `count` is already declared",
+        "1:5: error: `width` is a constant",
+        "1:5: error: `count` holds a number, which cannot be replaced by a string",
+        "1:5: error: `size` is not declared; use LET",
+        "1:5: error: `x` is built in and can only be changed by MOVE and SHIFT",
+        "1:6: error: `G` is built in and only changes when the terminal is resized",
+        "1:5: error: `foreground` is built in and cannot be changed",
+        "2:58: error: `width` is a constant",
+    ]);
+    // The procedure's declarations were its own
+    assert_eq!(run.variables.get("count"), Some(&Variable::Num(40)));
+    assert_eq!(run.variables.get("width"), Some(&Variable::Num(40)));
+    let run = run_script(crate::backend::VirtualScreen::new(24, 80), &["LET n = 1; PROC f; LET n = 5; END; CALL f; LIST out [n]"]);
+    assert_eq!(run.outcome.out, "[1]");
+    // A quoted number declares a string
+    let run = run_script(crate::backend::VirtualScreen::new(24, 80), &["LET zip = \"02134\"; STRING out zip; MAP cfg {z: zip, port: \"5432\"}"]);
+    assert_eq!(run.outcome.out, "02134");
+    assert_eq!(run.outcome.variables.get("cfg").unwrap().to_json(), "{\"port\":\"5432\",\"z\":\"02134\"}");
+}
+
+#[test]
+fn environment_test() {
+    let run = run_prepared(crate::backend::VirtualScreen::new(24, 80), |interpreter| {
+        interpreter.set_args(&["deploy".to_owned(), "prod".to_owned()]);
+        interpreter.define("title", "7");
+        interpreter.define("port", "8080");
+        interpreter.define("zip", "02134");
+        interpreter.define("count", "many");
+    }, &["ENV path \"PATH\"; ENV home \"PZX_TEST_UNSET\" DEFAULT \"/root\"; ENV empty \"PZX_TEST_UNSET\";
+CONST title = \"Setup\"; LET port = 80; LET zip = \"0\"; CALL len args INTO n; STRING out $2",
+        "LET count = 1", "SET title = \"x\"", "PUSH args \"c\""]);
+    assert_eq!(run.outcome.variables.get("path"), Some(&Variable::Str(std::env::var("PATH").unwrap_or_default())));
+    assert_eq!(run.outcome.variables.get("home"), Some(&Variable::Str("/root".to_owned())));
+    assert_eq!(run.outcome.variables.get("empty"), Some(&Variable::Str(String::new())));
+    // Values from the command line take the declared type, even when they read as numbers
+    assert_eq!(run.outcome.variables.get("title"), Some(&Variable::Str("7".to_owned())));
+    assert_eq!(run.outcome.variables.get("port"), Some(&Variable::Num(8080)));
+    assert_eq!(run.outcome.variables.get("zip"), Some(&Variable::Str("02134".to_owned())));
+    assert_eq!(run.outcome.variables.get("n"), Some(&Variable::Num(2)));
+    assert_eq!(run.outcome.out, "prod");
+    assert_eq!(run.errors, vec![
+        "1:5: error: `count` was given as \"many\", but is declared as a number",
+        "1:5: error: `title` is a constant",
+        "1:6: error: `args` is built in and comes from the command line",
+    ]);
+}
+
+#[test]
+fn exec_test() {
+    let run = run_script(crate::backend::VirtualScreen::new(24, 80), &["EXEC result \"echo one; echo two; echo oops >&2; exit 3\"; EXEC words [\"printf\", \"%s|\", \"a b\", 2];
+SHELL status \"exit 4\"; CALL lines result.stdout INTO lines", "EXEC missing []", "EXEC missing [\"pzx-no-such-program\"]"]);
+    assert_eq!(run.outcome.variables.get("result").unwrap().to_string(), "{status: 3, stderr: \"oops\", stdout: \"one\\ntwo\"}");
+    assert_eq!(run.outcome.variables.get("lines").unwrap().to_string(), "[\"one\", \"two\"]");
+    assert_eq!(run.outcome.variables.get("words").unwrap().to_string(), "{status: 0, stderr: \"\", stdout: \"a b|2|\"}");
+    assert_eq!(run.outcome.variables.get("status"), Some(&Variable::Num(4)));
+    assert_eq!(run.errors[0], "1:14: error: Improper operand provided to EXEC operator");
+    assert!(run.errors[1].starts_with("1:1: error: Could not run the command: "));
+}
+
+#[test]
+fn progress_test() {
+    let run = run_script(crate::backend::VirtualScreen::new(6, 40), &["MOVE 1 0; SPIN \"Waiting\" EXEC \"exit 2\" INTO spun; MOVE 2 0; GAUGE \"Copy\" 25;
+MOVE 3 0; GAUGE \"Install\" EXEC \"echo 30; echo XXX; echo 80; echo Almost there; echo XXX\" INTO installed"]);
+    assert_eq!(run.outcome.variables.get("spun"), Some(&Variable::Num(2)));
+    assert_eq!(run.outcome.variables.get("installed"), Some(&Variable::Num(0)));
+    assert_eq!(run.screen.row(1).trim_end(), "");
+    assert_eq!(run.screen.row(2), "Copy [#######---------------------]  25%");
+    assert_eq!(run.screen.row(3), "Almost there [################----]  80%");
+}
+
+#[test]
+fn tail_test() {
+    let path = std::env::temp_dir().join(format!("pzx-tail-test-{}.log", std::process::id()));
+    std::fs::write(&path, "started\nerror: disk\nretrying\nerror: network\n").unwrap();
+    let mut screen = crate::backend::VirtualScreen::new(6, 60);
+    screen.push_str("/error\n");
+    let run = run_script(screen, &[&format!("PRINT \"Deploying\"; TAIL log FROM FILE {:?}", path.to_str().unwrap())]);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(run.outcome.variables.get("log").unwrap().to_string(), "[\"started\", \"error: disk\", \"retrying\", \"error: network\"]");
+    // The lines start below the header, and only the ones holding the filter are shown
+    assert_eq!(run.screen.row(0), "Deploying");
+    assert_eq!(run.screen.row(1).trim_end(), "error: disk");
+    assert_eq!(run.screen.row(2).trim_end(), "error: network");
+    assert!(run.screen.row(5).starts_with(" 2 of 4 lines holding \"error\", following"));
+
+    // Standard output and standard error are both shown, in whatever order they arrive
+    let run = run_script(crate::backend::VirtualScreen::new(6, 40), &["TAIL output EXEC \"echo one; echo two >&2\""]);
+    let lines = run.outcome.variables.get("output").unwrap().to_string().replace("[\"two\", \"one\"]", "[\"one\", \"two\"]");
+    assert_eq!(lines, "[\"one\", \"two\"]");
+}
+
+#[test]
+fn pager_test() {
+    let mut screen = crate::backend::VirtualScreen::new(5, 30);
+    screen.push_str("/three\nq");
+    let run = run_script(screen, &["PRINT \"Notes\"; LET notes = \"one two three four five six seven eight nine ten\"; PAGER notes; PRINT \"done\"", "PAGER \"/no/such/file\""]);
+    // The text wraps below the header and scrolls to the match, and the script goes on below it
+    assert_eq!(run.screen.row(0), "Notes");
+    assert_eq!(run.screen.row(1).trim_end(), "one two three four five six s");
+    assert_eq!(run.screen.row(2).trim_end(), "even eight nine ten");
+    assert_eq!(run.screen.row(4).trim_end(), "done");
+    assert!(run.errors[0].contains("Could not read /no/such/file"));
+}
+
+#[test]
+fn form_test() {
+    let mut screen = crate::backend::VirtualScreen::new(8, 40);
+    screen.push_str("Ada\t42\t");
+    screen.push_key(Key::Right);
+    screen.push_str("\n");
+    let run = run_script(screen, &["PRINT \"Sign up\"; LET editors = [\"vi\", \"emacs\"]; FORM TEXT name \"Name\"; NUMBER age \"Age\"; CHOICE editor \"Editor\" editors; SUBMIT \"OK\" END; PRINT \"done\""]);
+    let values: Vec<String> = ["name", "age", "editor"].iter().map(|name| run.outcome.variables.get(*name).unwrap().to_string()).collect();
+    assert_eq!(values, vec!["\"Ada\"", "42", "\"emacs\""]);
+    // The fields start below the header, and the script goes on below the button
+    assert_eq!(run.screen.row(1).trim_end(), "Name:   Ada");
+    assert_eq!(run.screen.row(3).trim_end(), "Editor: < emacs >");
+    assert_eq!(run.screen.row(5).trim_end(), "        [ OK ]");
+    assert_eq!(run.screen.row(6).trim_end(), "done");
+
+    // A form shown again starts from what was entered, and without a keyboard keeps it
+    let run = run_script(crate::backend::VirtualScreen::new(8, 40), &["LET name = \"Ada\"; FORM TEXT name \"Name\" AT 2 4 END", "FORM TEXT name \"Name\" AT 2 4; CHOICE c \"C\" [] END"]);
+    assert_eq!(run.outcome.variables.get("name").unwrap().to_string(), "\"Ada\"");
+    assert_eq!(run.screen.row(2).trim_end(), "    Name: Ada");
+    assert!(run.errors[0].contains("`[]` has no options to choose from"));
+}
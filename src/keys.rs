@@ -0,0 +1,103 @@
+use std::collections::VecDeque;
+use std::thread;
+use std::time::Duration;
+
+use crate::backend::Key;
+
+// Turns a sequence such as `y<Enter>John<Tab><Down><Enter>` into keys.
+// Names in angle brackets are case-insensitive and `<lt>` stands for a literal `<`.
+// Line breaks are ignored so that long sequences can be split across the lines of a file.
+pub fn parse_keys(raw: &str) -> Result<Vec<Key>, String> {
+    let mut res: Vec<Key> = Vec::new();
+
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '<' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('>') => break,
+                        Some(c) => name.push(c),
+                        None => return Err(format!("Key name <{} is never closed", name)),
+                    }
+                }
+                res.push(named_key(&name).ok_or(format!("Unknown key name <{}>", name))?);
+            },
+            '\n' | '\r' => {
+                continue;
+            },
+            _ => {
+                res.push(Key::Char(c));
+            },
+        }
+    }
+
+    Ok(res)
+}
+
+fn named_key(name: &str) -> Option<Key> {
+    let key = match name.to_lowercase().as_str() {
+        "enter" | "return" | "cr" => Key::Enter,
+        "tab" => Key::Tab,
+        "backtab" | "s-tab" => Key::BackTab,
+        "backspace" | "bs" => Key::Backspace,
+        "delete" | "del" => Key::Delete,
+        "escape" | "esc" => Key::Escape,
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" | "pgup" => Key::PageUp,
+        "pagedown" | "pgdn" => Key::PageDown,
+        "resize" => Key::Resize,
+        "space" => Key::Char(' '),
+        "lt" => Key::Char('<'),
+        "gt" => Key::Char('>'),
+        _ => {
+            return None;
+        },
+    };
+
+    Some(key)
+}
+
+// Keys handed to the program in place of the keyboard. Once they run out the program
+// sees Key::Eof, which ends any pending input, so a replayed run always finishes.
+pub struct Replay {
+    keys: VecDeque<Key>,
+    delay: Duration,
+}
+
+impl Replay {
+    pub fn new(keys: Vec<Key>, delay: Duration) -> Replay {
+        Replay {
+            keys: keys.into_iter().collect(),
+            delay,
+        }
+    }
+
+    pub fn next_key(&mut self) -> Key {
+        if !self.delay.is_zero() {
+            thread::sleep(self.delay);
+        }
+        self.keys.pop_front().unwrap_or(Key::Eof)
+    }
+}
+
+#[test]
+fn parse_keys_test() {
+    assert_eq!(parse_keys("y<Enter>Jo<tab><Down><lt>\n").unwrap(), vec![
+        Key::Char('y'),
+        Key::Enter,
+        Key::Char('J'),
+        Key::Char('o'),
+        Key::Tab,
+        Key::Down,
+        Key::Char('<'),
+    ]);
+    assert!(parse_keys("<Nope>").is_err());
+    assert!(parse_keys("<Enter").is_err());
+}
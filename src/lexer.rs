@@ -0,0 +1,377 @@
+use crate::diagnostic::{Diagnostic, Span};
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Operator {
+    Print,
+    Await,
+    Accept,
+    Foreground,
+    Background,
+    Move,
+    Shift,
+    StringAssign,
+    NumberAssign,
+    ColorAssign,
+    On,
+    End,
+    SavePosition,
+    RestorePosition,
+    Snapshot,
+    Call,
+    Proc,
+    Return,
+    Include,
+    ListAssign,
+    Push,
+    Pop,
+    For,
+    MapAssign,
+    BoolAssign,
+    Confirm,
+    If,
+    Else,
+    Let,
+    Const,
+    Set,
+    Env,
+    Exec,
+    Shell,
+    Spin,
+    Gauge,
+    Tail,
+    Pager,
+    Form,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Comparison::Equal => "==",
+            Comparison::NotEqual => "!=",
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+        }
+    }
+}
+
+impl Operator {
+    // The keyword as it is written in instructions
+    pub fn name(self) -> &'static str {
+        match self {
+            Operator::Print => "PRINT",
+            Operator::Await => "AWAIT",
+            Operator::Accept => "ACCEPT",
+            Operator::Foreground => "FORE",
+            Operator::Background => "BACK",
+            Operator::Move => "MOVE",
+            Operator::Shift => "SHIFT",
+            Operator::StringAssign => "STRING",
+            Operator::NumberAssign => "NUM",
+            Operator::ColorAssign => "CLR",
+            Operator::On => "ON",
+            Operator::End => "END",
+            Operator::SavePosition => "SAVEPOS",
+            Operator::RestorePosition => "RESTOREPOS",
+            Operator::Snapshot => "SNAPSHOT",
+            Operator::Call => "CALL",
+            Operator::Proc => "PROC",
+            Operator::Return => "RETURN",
+            Operator::Include => "INCLUDE",
+            Operator::ListAssign => "LIST",
+            Operator::Push => "PUSH",
+            Operator::Pop => "POP",
+            Operator::For => "FOR",
+            Operator::MapAssign => "MAP",
+            Operator::BoolAssign => "BOOL",
+            Operator::Confirm => "CONFIRM",
+            Operator::If => "IF",
+            Operator::Else => "ELSE",
+            Operator::Let => "LET",
+            Operator::Const => "CONST",
+            Operator::Set => "SET",
+            Operator::Env => "ENV",
+            Operator::Exec => "EXEC",
+            Operator::Shell => "SHELL",
+            Operator::Spin => "SPIN",
+            Operator::Gauge => "GAUGE",
+            Operator::Tail => "TAIL",
+            Operator::Pager => "PAGER",
+            Operator::Form => "FORM",
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum Token {
+    Operator(Operator),
+    Separator,
+    Identifier(String),
+    Literal(String, bool), // Just a string, and whether it was quoted; gets converted to a value in the parser
+    Call(String), // A function called for its value, as in `version()`
+    Punctuation(char), // The brackets, braces, commas and colons of lists and maps
+    Comparison(Comparison),
+}
+
+// Splits instructions into tokens, each with where it was found
+pub struct Lexer<'a> {
+    raw: &'a str,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(raw: &'a str) -> Lexer<'a> {
+        Lexer { raw }
+    }
+
+    pub fn tokens(&self) -> Result<Vec<(Token, Span)>, Diagnostic> {
+        derive_tokens(self.raw)
+    }
+}
+
+pub fn derive_tokens(raw: &str) -> Result<Vec<(Token, Span)>, Diagnostic> {
+    let mut res: Vec<(Token, Span)> = Vec::new();
+
+    let mut buffer: String = String::new();
+    let mut span = Span::default();
+    let mut in_quotes = false;
+    let mut line = 1;
+    let mut column = 1;
+    for (i, c) in raw.char_indices() {
+        // Remember where the token being assembled began
+        if buffer.is_empty() {
+            span = Span { line, column, start: i, end: i, file: 0 };
+        }
+
+        match c { // Check for separators and assemble tokens afterwards
+            '"' => { // We want to accept ANY INPUT in quotes, so lexing stops there
+                buffer.push(c);
+                in_quotes = !in_quotes;
+            },
+            // Scripts read from files spread their instructions over lines and indent them
+            ' ' | '\t' | '\n' | '\r' if !in_quotes => {
+                // This occurs when a space immediately follows a semicolon (common for readability)
+                if !buffer.is_empty() {
+                    span.end = i;
+                    res.push((assemble_token(&buffer), span));
+                    buffer = String::new();
+                }
+            },
+            ';' if !in_quotes => {
+                // This occurs when a semicolon immediately follows a space, as in `END ;`
+                if !buffer.is_empty() {
+                    span.end = i;
+                    res.push((assemble_token(&buffer), span));
+                    buffer = String::new();
+                }
+                res.push((Token::Separator, Span { line, column, start: i, end: i + 1, file: 0 }));
+            },
+            // These stand on their own even without spaces around them, as in `xs[0]` or `{a:1}`
+            '[' | ']' | '{' | '}' | ',' | ':' if !in_quotes => {
+                if !buffer.is_empty() {
+                    span.end = i;
+                    res.push((assemble_token(&buffer), span));
+                    buffer = String::new();
+                }
+                res.push((Token::Punctuation(c), Span { line, column, start: i, end: i + 1, file: 0 }));
+            },
+            _ => {
+                buffer.push(c);
+            },
+        }
+
+        if c == '\n' {
+            line += 1;
+            column = 1;
+        } else {
+            column += 1;
+        }
+    }
+
+    if in_quotes {
+        return Err(Diagnostic::error(span, "Quote is never closed".to_owned()));
+    }
+
+    // In case the command list does not end in a separator
+    if !buffer.is_empty() {
+        span.end = raw.len();
+        res.push((assemble_token(&buffer), span));
+    }
+
+    Ok(res)
+}
+
+// Words are classified on their own; which operands an operator accepts is up to the parser.
+// Only the words that start a statement are operators. Those that join its operands, such as
+// INTO or FROM, and TRUE and FALSE are identifiers that the parser only takes as keywords where it
+// expects them.
+fn assemble_token(raw: &str) -> Token {
+    let res = match raw {
+        "PRINT" => Some(Operator::Print),
+        "AWAIT" => Some(Operator::Await),
+        "ACCEPT" => Some(Operator::Accept),
+        "FORE" => Some(Operator::Foreground),
+        "BACK" => Some(Operator::Background),
+        "MOVE" => Some(Operator::Move),
+        "SHIFT" => Some(Operator::Shift),
+        "STRING" => Some(Operator::StringAssign),
+        "NUM" => Some(Operator::NumberAssign),
+        "CLR" => Some(Operator::ColorAssign),
+        "ON" => Some(Operator::On),
+        "END" => Some(Operator::End),
+        "SAVEPOS" => Some(Operator::SavePosition),
+        "RESTOREPOS" => Some(Operator::RestorePosition),
+        "SNAPSHOT" => Some(Operator::Snapshot),
+        "CALL" => Some(Operator::Call),
+        "PROC" => Some(Operator::Proc),
+        "RETURN" => Some(Operator::Return),
+        "INCLUDE" => Some(Operator::Include),
+        "LIST" => Some(Operator::ListAssign),
+        "PUSH" => Some(Operator::Push),
+        "POP" => Some(Operator::Pop),
+        "FOR" => Some(Operator::For),
+        "MAP" => Some(Operator::MapAssign),
+        "BOOL" => Some(Operator::BoolAssign),
+        "CONFIRM" => Some(Operator::Confirm),
+        "IF" => Some(Operator::If),
+        "ELSE" => Some(Operator::Else),
+        "LET" => Some(Operator::Let),
+        "CONST" => Some(Operator::Const),
+        "SET" => Some(Operator::Set),
+        "ENV" => Some(Operator::Env),
+        "EXEC" => Some(Operator::Exec),
+        "SHELL" => Some(Operator::Shell),
+        "SPIN" => Some(Operator::Spin),
+        "GAUGE" => Some(Operator::Gauge),
+        "TAIL" => Some(Operator::Tail),
+        "PAGER" => Some(Operator::Pager),
+        "FORM" => Some(Operator::Form),
+        _ => None,
+    };
+
+    if let Some(operator) = res {
+        return Token::Operator(operator);
+    }
+
+    let comparison = match raw {
+        "==" => Some(Comparison::Equal),
+        "!=" => Some(Comparison::NotEqual),
+        "<" => Some(Comparison::Less),
+        "<=" => Some(Comparison::LessOrEqual),
+        ">" => Some(Comparison::Greater),
+        ">=" => Some(Comparison::GreaterOrEqual),
+        _ => None,
+    };
+
+    if let Some(comparison) = comparison {
+        return Token::Comparison(comparison);
+    }
+
+    if raw == "=" {
+        return Token::Punctuation('=');
+    }
+
+    // Quoted text and numbers (negative ones included) are literals; everything else names a variable
+    let mut chars = raw.chars();
+    let first = chars.next().unwrap();
+    if first == '"' {
+        Token::Literal(raw.replace('"', ""), true)
+    } else if first.is_ascii_digit() || (first == '-' && chars.next().map(|c| c.is_ascii_digit()).unwrap_or(false)) {
+        Token::Literal(raw.to_owned(), false)
+    } else if raw.len() > 2 && raw.ends_with("()") {
+        Token::Call(raw[..raw.len() - 2].to_owned())
+    } else {
+        Token::Identifier(raw.to_owned())
+    }
+}
+
+#[test]
+fn lexer_test() {
+    let kinds = |raw: &str| -> Vec<Token> { derive_tokens(raw).unwrap().into_iter().map(|(token, _)| token).collect() };
+    let identifier = |s: &str| Token::Identifier(s.to_owned());
+    let text = |s: &str| Token::Literal(s.to_owned(), true);
+    let number = |s: &str| Token::Literal(s.to_owned(), false);
+
+    assert_eq!(kinds("PRINT \"hi\""), vec![Token::Operator(Operator::Print), text("hi")]);
+    assert_eq!(kinds("PRINT \"hello world\""), vec![Token::Operator(Operator::Print), text("hello world")]);
+    assert_eq!(kinds("STRING a \"hi\"; PRINT a"), vec![
+        Token::Operator(Operator::StringAssign), identifier("a"), text("hi"), Token::Separator, Token::Operator(Operator::Print), identifier("a"),
+    ]);
+    assert_eq!(kinds("FORE green; BACK bright_red; PRINT \"colors\""), vec![
+        Token::Operator(Operator::Foreground), identifier("green"), Token::Separator,
+        Token::Operator(Operator::Background), identifier("bright_red"), Token::Separator, Token::Operator(Operator::Print), text("colors"),
+    ]);
+    assert_eq!(kinds("MOVE 0 0"), vec![Token::Operator(Operator::Move), number("0"), number("0")]);
+    assert_eq!(kinds("SHIFT 0 0"), vec![Token::Operator(Operator::Shift), number("0"), number("0")]);
+    assert_eq!(kinds("NUM a 0"), vec![Token::Operator(Operator::NumberAssign), identifier("a"), number("0")]);
+}
+
+#[test]
+fn span_test() {
+    let tokens = derive_tokens("PRINT \"a b\";\n  MOVE -1 $").unwrap();
+    let expected = vec![
+        (Token::Operator(Operator::Print), Span { line: 1, column: 1, start: 0, end: 5, file: 0 }),
+        (Token::Literal("a b".to_owned(), true), Span { line: 1, column: 7, start: 6, end: 11, file: 0 }),
+        (Token::Separator, Span { line: 1, column: 12, start: 11, end: 12, file: 0 }),
+        (Token::Operator(Operator::Move), Span { line: 2, column: 3, start: 15, end: 19, file: 0 }),
+        (Token::Literal("-1".to_owned(), false), Span { line: 2, column: 8, start: 20, end: 22, file: 0 }),
+        (Token::Identifier("$".to_owned()), Span { line: 2, column: 11, start: 23, end: 24, file: 0 }),
+    ];
+    assert_eq!(tokens, expected);
+    assert!(derive_tokens("PRINT \"open").is_err());
+}
+
+#[test]
+fn punctuation_test() {
+    let tokens = derive_tokens("LIST xs [\"a, b\",2]; MAP cfg {port:80}; NUM n xs[0]").unwrap();
+    let kinds: Vec<Token> = tokens.iter().map(|(token, _)| token.clone()).collect();
+    assert_eq!(kinds, vec![
+        Token::Operator(Operator::ListAssign), Token::Identifier("xs".to_owned()), Token::Punctuation('['),
+        Token::Literal("a, b".to_owned(), true), Token::Punctuation(','), Token::Literal("2".to_owned(), false), Token::Punctuation(']'), Token::Separator,
+        Token::Operator(Operator::MapAssign), Token::Identifier("cfg".to_owned()), Token::Punctuation('{'),
+        Token::Identifier("port".to_owned()), Token::Punctuation(':'), Token::Literal("80".to_owned(), false), Token::Punctuation('}'), Token::Separator,
+        Token::Operator(Operator::NumberAssign), Token::Identifier("n".to_owned()), Token::Identifier("xs".to_owned()),
+        Token::Punctuation('['), Token::Literal("0".to_owned(), false), Token::Punctuation(']'),
+    ]);
+    // An index directly follows what it indexes, which is how the parser tells it from a list
+    let spans: Vec<(usize, usize)> = tokens[18..].iter().map(|(_, span)| (span.start, span.end)).collect();
+    assert_eq!(spans, vec![(45, 47), (47, 48), (48, 49), (49, 50)]);
+}
+
+#[test]
+fn comparison_test() {
+    let kinds: Vec<Token> = derive_tokens("BOOL b x <= -1; IF b != TRUE; LET v = version()").unwrap().into_iter().map(|(token, _)| token).collect();
+    assert_eq!(kinds, vec![
+        Token::Operator(Operator::BoolAssign), Token::Identifier("b".to_owned()), Token::Identifier("x".to_owned()),
+        Token::Comparison(Comparison::LessOrEqual), Token::Literal("-1".to_owned(), false), Token::Separator,
+        Token::Operator(Operator::If), Token::Identifier("b".to_owned()), Token::Comparison(Comparison::NotEqual), Token::Identifier("TRUE".to_owned()), Token::Separator,
+        Token::Operator(Operator::Let), Token::Identifier("v".to_owned()), Token::Punctuation('='), Token::Call("version".to_owned()),
+    ]);
+}
+
+#[test]
+fn keyword_test() {
+    let raw = "SNAPSHOT p FORMAT html; CALL f INTO r; FOR i IN xs; ENV h \"HOME\" DEFAULT \"/\"; SPIN \"s\" EXEC c; TAIL t FROM FILE p; FORM TEXT a \"A\" AT 1 2 END";
+    let tokens = derive_tokens(raw).unwrap();
+    let operators: Vec<&str> = tokens.iter().filter_map(|(token, _)| match token {
+        Token::Operator(operator) => Some(operator.name()),
+        _ => None,
+    }).collect();
+    assert_eq!(operators, vec!["SNAPSHOT", "CALL", "FOR", "ENV", "SPIN", "EXEC", "TAIL", "FORM", "END"]);
+    // The words in between are left for the parser to recognize
+    let words: Vec<&str> = tokens.iter().filter_map(|(token, _)| match token {
+        Token::Identifier(word) if word.chars().all(|c| c.is_ascii_uppercase()) => Some(word.as_str()),
+        _ => None,
+    }).collect();
+    assert_eq!(words, vec!["FORMAT", "INTO", "IN", "DEFAULT", "FROM", "FILE", "TEXT", "AT"]);
+}
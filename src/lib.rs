@@ -0,0 +1,28 @@
+extern crate ncurses;
+
+pub mod backend;
+pub mod check;
+pub mod debug;
+pub mod diagnostic;
+pub mod dump;
+mod error;
+pub mod form;
+pub mod functions;
+pub mod include;
+mod interpreter;
+pub mod keys;
+pub mod lexer;
+pub mod pager;
+pub mod parser;
+pub mod progress;
+pub mod repl;
+pub mod runner;
+pub mod snapshot;
+pub mod tail;
+pub mod trace;
+
+pub use error::PzxError;
+pub use functions::Function;
+pub use interpreter::{Interpreter, Outcome, Variable};
+pub use lexer::Lexer;
+pub use parser::{Parser, Program};
@@ -1,623 +1,253 @@
-#![allow(unused_variables)]
-#![allow(unused_mut)]
-#![allow(unused_assignments)]
-#![allow(dead_code)]
+mod cli;
 
-extern crate ncurses;
-
-use std::str;
 use std::env;
-use std::collections::HashMap;
-
-use ncurses::*;
+use std::io::{self, IsTerminal};
+use std::process;
+
+use pzx::{check, debug, dump, include, repl, runner, snapshot};
+use pzx::backend::{Backend, NcursesBackend, VirtualScreen};
+use pzx::debug::Debugger;
+use pzx::diagnostic::Diagnostic;
+use pzx::keys::Replay;
+use pzx::lexer::Lexer;
+use pzx::repl::Repl;
+use pzx::snapshot::Format;
+use pzx::trace::{Trace, TracingBackend};
+use pzx::{Interpreter, Program, PzxError};
+
+use cli::{Mode, Options};
 
 fn main() {
-    init();
-    let global_vars = global_variables();
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        terminate();
-        panic!("ERR: No instructions provided");
-    }
-    let commands = &args[1];
-    let tokens = derive_tokens(commands);
-    let ret = parse_tokens(&tokens, global_vars);
-    terminate();
-
-    println!("{}", ret);
-}
-
-fn init() {
-    initscr();
-    curs_set(CURSOR_VISIBILITY::CURSOR_INVISIBLE);
-    cbreak();
-    keypad(stdscr(), true); 
-    noecho();
-
-    if has_colors() && can_change_color() {
-        start_color();
-    }
-
-    clear();
-}
-
-fn terminate() {
-    endwin();
-}
-
-#[derive(Eq, PartialEq, Debug)]
-enum Operator {
-    Print,
-    Await,
-    Accept,
-    Foreground,
-    Background,
-    Move,
-    Shift,
-    StringAssign,
-    NumberAssign,
-    ColorAssign,
-}
-
-#[derive(Eq, PartialEq, Debug)]
-enum Token {
-    Operator(Operator),
-    Separator, 
-    Identifier(String),
-    Literal(String), // Just a string; gets converted to a value in the parser
-}
-
-#[derive(Eq, PartialEq)]
-enum Variable {
-    Num(i32),
-    Str(String),
-    Clr {
-        r: i16,
-        g: i16,
-        b: i16,
-    },
-}
-
-fn parse_tokens(tokens: &Vec<Token>, global_vars: HashMap<String, Variable>) -> String {
-    let mut variables = global_vars;
-
-    for i in 0..tokens.len() {
-        match &tokens[i] {
-            Token::Operator(Operator::Print) => {
-                if let Token::Literal(s) = &tokens[i+1] {
-                    addstr(s);
-                } else if let Token::Identifier(var) = &tokens[i+1] {
-                    if let Variable::Str(s) = variables.get(var).unwrap() {
-                        addstr(s);
-                    }
-                } else {
-                    panic!("Err: Improper operand provided to PRINT operation");
-                }
-            },
-            Token::Operator(Operator::Await) => {
-                if let Token::Identifier(var) = &tokens[i+1] {
-                    echo();
-                    curs_set(CURSOR_VISIBILITY::CURSOR_VISIBLE);
-                    let mut operand = String::new();
-                    getstr(&mut operand);
-                    noecho();
-                    curs_set(CURSOR_VISIBILITY::CURSOR_INVISIBLE);
-                    variables.insert(var.to_owned(), Variable::Str(operand));
-                } else {
-                    panic!("Err: Improper operand provided to AWAIT operation");
-                }
-            },
-            Token::Operator(Operator::Accept) => {
-                if let Token::Identifier(var) = &tokens[i+1] {
-                    echo();
-                    curs_set(CURSOR_VISIBILITY::CURSOR_VISIBLE);
-                    let operand = getch() as u8 as char;
-                    noecho();
-                    curs_set(CURSOR_VISIBILITY::CURSOR_INVISIBLE);
-                    variables.insert(var.to_owned(), Variable::Str(operand.to_string()));
-                } else {
-                    panic!("Err: Improper operand provided to AWAIT operator");
-                }
-            },
-            Token::Operator(Operator::Foreground) => {
-                if let Token::Identifier(var) = &tokens[i+1] {
-                    change_foreground(&variables.get(var).unwrap()).expect("Err: Could not change foreground color");
-                } else if let Token::Literal(r_raw) = &tokens[i+1] {
-                    let mut r: i16 = 0;
-                    let mut g: i16 = 0;
-                    let mut b: i16 = 0;
-                    r = r_raw.parse::<i16>().expect("Err: Color literal not composed of digits");
-                    if let Token::Literal(g_raw) = &tokens[i+2] {
-                        g = g_raw.parse::<i16>().expect("Err: Color literal not composed of digits");
-                    }
-                    if let Token::Literal(b_raw) = &tokens[i+3] {
-                        b = b_raw.parse::<i16>().expect("Err: Color literal not composed of digits");
-                    }
-                    change_foreground(&Variable::Clr{r, g, b}).expect("Err: Could not change foreground color");
-                } else {
-                    panic!("Err: Improper operand provided to FORE operator");
-                }
-            },
-            Token::Operator(Operator::Background) => {
-                if let Token::Identifier(var) = &tokens[i+1] {
-                    change_background(&variables.get(var).unwrap()).expect("Err: Could not change foreground color");
-                } else if let Token::Literal(r_raw) = &tokens[i+1] {
-                    let mut r: i16 = 0;
-                    let mut g: i16 = 0;
-                    let mut b: i16 = 0;
-                    r = r_raw.parse::<i16>().expect("Err: Color literal not composed of digits");
-                    if let Token::Literal(g_raw) = &tokens[i+2] {
-                        g = g_raw.parse::<i16>().expect("Err: Color literal not composed of digits");
-                    }
-                    if let Token::Literal(b_raw) = &tokens[i+3] {
-                        b = b_raw.parse::<i16>().expect("Err: Color literal not composed of digits");
-                    }
-                    change_background(&Variable::Clr{r, g, b}).expect("Err: Could not change foreground color");
-                } else {
-                    panic!("Err: Improper operand provided to FORE operator");
-                }
-            },
-            Token::Operator(Operator::Move) => {
-                // First get the desired position
-                let mut pos = (0, 0);
-                match &tokens[i+1] {
-                    Token::Literal(s) => {
-                        pos.0 = s.parse().expect("Err: Improper operand provided to MOVE operator");
-                    },
-                    Token::Identifier(var) => {
-                        let line_num = variables.get(var).expect("Err: Improper operand provided to MOVE operator");
-                        if let Variable::Num(i) = line_num {
-                           pos.0 = *i; 
-                        }
-                    },
-                    _ => {
-                        panic!("Err: Improper operand provided to MOVE operator");
-                    },
-                }
-
-                match &tokens[i+2] {
-                    Token::Literal(s) => {
-                        pos.1 = s.parse().expect("Err: Improper operand provided to MOVE operator");
-                    },
-                    Token::Identifier(var) => {
-                        let line_num = variables.get(var).expect("Err: Improper operand provided to MOVE operator");
-                        if let Variable::Num(i) = line_num {
-                           pos.1 = *i; 
-                        }
-                    },
-                    _ => {
-                        panic!("Err: Improper operand provided to MOVE operator");
-                    },
-                }
-                // Then move to it
-                mv(pos.0, pos.1);
-                *variables.get_mut("x").unwrap() = Variable::Num(pos.1);
-                *variables.get_mut("y").unwrap() = Variable::Num(pos.0);
-            },
-            Token::Operator(Operator::Shift) => {
-                // First get the desired position
-                let mut pos = (0, 0);
-                match &tokens[i+1] {
-                    Token::Literal(s) => {
-                        pos.0 = s.parse().expect("Err: Improper operand provided to MOVE operator");
-                    },
-                    Token::Identifier(var) => {
-                        let line_num = variables.get(var).expect("Err: Improper operand provided to MOVE operator");
-                        if let Variable::Num(y) = line_num {
-                           pos.0 = *y; 
-                        }
-                    },
-                    _ => {
-                        panic!("Err: Improper operand provided to MOVE operator");
-                    },
-                }
-
-                match &tokens[i+2] {
-                    Token::Literal(s) => {
-                        pos.1 = s.parse().expect("Err: Improper operand provided to MOVE operator");
-                    },
-                    Token::Identifier(var) => {
-                        let line_num = variables.get(var).expect("Err: Improper operand provided to MOVE operator");
-                        if let Variable::Num(x) = line_num {
-                           pos.1 = *x; 
-                        }
-                    },
-                    _ => {
-                        panic!("Err: Improper operand provided to MOVE operator");
-                    },
-                }
-                // Shift as offset by current cursor position
-                if let Variable::Num(y) = variables.get("y").unwrap() {
-                    pos.0 += y;
-                }
-                if let Variable::Num(x) = variables.get("x").unwrap() {
-                    pos.1 += x;
-                }
-                // Then move to it
-                mv(pos.0, pos.1);
-                *variables.get_mut("x").unwrap() = Variable::Num(pos.1);
-                *variables.get_mut("y").unwrap() = Variable::Num(pos.0);
-            },
-            Token::Operator(Operator::StringAssign) => {
-                let mut value = String::new();
-
-                match &tokens[i+2] {
-                    Token::Identifier(var) => {
-                        if let Variable::Str(s) = variables.get(var).unwrap() {
-                            value = s.to_owned();
-                        } else {
-                            panic!("Err: Improper operand provided to STRING operator");
-                        }
-                    },
-                    Token::Literal(s) => {
-                        value = s.to_owned();
-                    }
-                    _ => {
-                        panic!("Err: Improper operand provided to STRING operator");
-                    },
-                }
+    let options = match cli::parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Err: {}", e);
+            eprintln!("{}", cli::USAGE);
+            process::exit(1);
+        },
+    };
 
-                if let Token::Identifier(var) = &tokens[i+1] {
-                    *variables.get_mut(var).unwrap() = Variable::Str(value);
-                }
+    if let Mode::Test(dir) = &options.mode {
+        match runner::run_tests(dir, options.bless, options.size) {
+            Ok(results) => {
+                print!("{}", runner::report(&results, io::stdout().is_terminal()));
+                process::exit(if runner::passed(&results) { 0 } else { 1 });
             },
-            Token::Operator(Operator::NumberAssign) => {
-                let mut value: i32;
-
-                match &tokens[i+2] {
-                    Token::Identifier(var) => {
-                        if let Variable::Num(x) = variables.get(var).unwrap() {
-                            value = *x;
-                        } else {
-                            panic!("Err: Improper operand provided to NUM operator");
-                        }
-                    },
-                    Token::Literal(s) => {
-                        value = s.parse().expect("Err: Improper operand provided to NUM operator");
-                    }
-                    _ => {
-                        panic!("Err: Improper operand provided to NUM operator");
-                    },
-                }
-
-                if let Token::Identifier(var) = &tokens[i+1] {
-                    *variables.get_mut(var).unwrap() = Variable::Num(value);
-                }
+            Err(e) => {
+                eprintln!("Err: {}", e);
+                process::exit(1);
             },
-            Token::Operator(Operator::ColorAssign) => {
-                let mut r: i16 = 0;
-                let mut g: i16 = 0;
-                let mut b: i16 = 0;
-                let mut name = String::new();
-
-                if let Token::Identifier(s) = &tokens[i+1] {
-                    name = s.to_owned();
-                } else {
-                    panic!("Err: Improper operand provided to CLR operator");
-                }
-
-                if let Token::Literal(r_var) = &tokens[i+2] {
-                    r = r_var.parse::<i16>().expect("Err: Improper operand provided to CLR operator");
-                } else {
-                    panic!("Err: Improper operand provided to CLR operator");
-                }
-
-                if let Token::Literal(g_var) = &tokens[i+3] {
-                    g = g_var.parse::<i16>().expect("Err: Improper operand provided to CLR operator");
-                } else {
-                    panic!("Err: Improper operand provided to CLR operator");
-                }
-
-                if let Token::Literal(b_var) = &tokens[i+4] {
-                    b = b_var.parse::<i16>().expect("Err: Improper operand provided to CLR operator");
-                } else {
-                    panic!("Err: Improper operand provided to CLR operator");
-                }
+        }
+    }
 
-                variables.insert(name, Variable::Clr{r, g, b});
-            },
-            Token::Identifier(_) => {
-                continue;
+    if let Mode::DumpTokens = options.mode {
+        match Lexer::new(&options.commands).tokens() {
+            Ok(tokens) => {
+                print!("{}", dump::tokens(&tokens, options.json));
+                process::exit(0);
             },
-            Token::Literal(_) => {
-                continue;
-            },
-            Token::Separator => {
-                continue;
+            Err(diagnostic) => {
+                report(&options, None, &[diagnostic]);
+                process::exit(1);
             },
         }
     }
 
-    if let Variable::Str(s) = variables.get("out").unwrap() {
-        return s.to_owned();
-    } else {
-        return "".to_owned();
-    }
-}
+    // Problems in the instructions are reported before the terminal is ever touched
+    let program = match Program::parse(&options.commands) {
+        Ok(program) => program,
+        Err(e) => {
+            report(&options, None, &e.diagnostics());
+            process::exit(1);
+        },
+    };
 
-fn change_foreground(color: &Variable) -> Result<(), ()> {
-    if let Variable::Clr{r, g, b} = color {
-        init_color(16, *r, *g, *b);
-    } else {
-        return Err(());
+    if let Mode::DumpAst = options.mode {
+        print!("{}", dump::program(&program, options.json));
+        process::exit(0);
     }
 
-    return Ok(());
-}
+    let program = match include::expand(program, options.script.as_deref()) {
+        Ok(program) => program,
+        Err(e) => {
+            report(&options, None, &e.diagnostics());
+            process::exit(1);
+        },
+    };
 
-fn change_background(color: &Variable) -> Result<(), ()> {
-    if let Variable::Clr{r, g, b} = color {
-        init_color(17, *r, *g, *b);
-    } else {
-        return Err(());
+    if let Mode::Check = options.mode {
+        let diagnostics = check::check(&program);
+        report(&options, Some(&program), &diagnostics);
+        process::exit(if diagnostics.is_empty() { 0 } else { 1 });
     }
 
-    return Ok(());
-}
+    let trace = match &options.trace {
+        Some(path) => match Trace::create(path, options.log_level) {
+            Ok(trace) => Some(trace),
+            Err(e) => {
+                eprintln!("Err: Could not create {}: {}", path, e);
+                process::exit(1);
+            },
+        },
+        None => None,
+    };
 
-fn update_color_pairs(foreground: &Variable, background: &Variable) {
-    change_foreground(foreground).expect("Could not change foreground color");
-    change_background(background).expect("Could not change background color");
+    // The terminal leaves curses mode when the backend is dropped, before anything is printed
+    let ret = if options.headless {
+        let mut screen = VirtualScreen::new(options.size.0, options.size.1);
+        run(&options, &program, &mut screen, trace, None)
+    } else if options.debug {
+        let mut terminal = NcursesBackend::new();
+        let (mut viewport, pane) = split(&mut terminal, debug::PANE_LINES);
+        let debugger = Debugger::new(Box::new(pane), &options.commands, options.breakpoints.clone());
+        run(&options, &program, &mut viewport, trace, Some(Pane::Debugger(debugger)))
+    } else if let Mode::Repl = options.mode {
+        let mut terminal = NcursesBackend::new();
+        let (mut viewport, pane) = split(&mut terminal, repl::PANE_LINES);
+        run(&options, &program, &mut viewport, trace, Some(Pane::Repl(Repl::new(Box::new(pane)))))
+    } else {
+        let mut backend = NcursesBackend::new();
+        run(&options, &program, &mut backend, trace, None)
+    };
 
-    init_pair(1, 16, 17);
-    bkgd(' ' as chtype | COLOR_PAIR(1) as chtype);
-    attron(COLOR_PAIR(1));
-    clear();
+    // Errors are reported once the terminal is back to normal
+    match ret {
+        Ok(out) => println!("{}", out),
+        Err(Failure::Program(e)) => {
+            report(&options, Some(&program), &e.diagnostics());
+            process::exit(1);
+        },
+        // The program itself ran, so what it printed still counts
+        Err(Failure::Snapshot(out, path, e)) => {
+            println!("{}", out);
+            eprintln!("Err: Could not write snapshot to {}: {}", path, e);
+            process::exit(1);
+        },
+    }
 }
 
-fn global_variables() -> HashMap<String, Variable> {
-    let mut res: HashMap<String, Variable> = HashMap::new();
-    // Cursor position 
-    res.insert("x".to_owned(), Variable::Num(0));
-    res.insert("y".to_owned(), Variable::Num(0));
-    // Screen-relative positions
-    res.insert("G".to_owned(), Variable::Num(LINES()-1));
-    res.insert("$".to_owned(), Variable::Num(COLS()-1));
-    // Colors
-    res.insert("foreground".to_owned(), Variable::Clr{r: 1000, g: 1000, b: 1000});
-    res.insert("background".to_owned(), Variable::Clr{r: 0, g: 0, b: 0});
-    update_color_pairs(res.get("foreground").unwrap(), res.get("background").unwrap());
-    // Standard output
-    res.insert("out".to_owned(), Variable::Str(String::new()));
-    return res;
+// Why a run did not succeed: the program failed, or the screen it left could not be saved
+#[derive(Debug)]
+enum Failure {
+    Program(PzxError),
+    Snapshot(String, String, io::Error), // What the program printed, the path and what went wrong
 }
 
-fn derive_tokens(raw: &str) -> Vec<Token> {
-    let mut res: Vec<Token> = Vec::new();
-    
-    let mut buffer: String = String::new();
-    let mut in_quotes = false;
-    for c in raw.chars() {
-        match c { // Check for separators and assemble tokens afterwards
-            '"' => { // We want to accept ANY INPUT in quotes, so lexing stops there
-                buffer.push(c);
-                in_quotes = !in_quotes;
-            },
-            ' ' => {
-                if !in_quotes {
-                    // This occurs when a space immediately follows a semicolon (common for readability)
-                    if buffer.is_empty() {
-                        continue;
-                    }
-                    let token = assemble_token(&buffer, &res);
-                    buffer = String::new();
-                    res.push(token.expect("Err: Could not lex token"));
-                } else {
-                    buffer.push(c);
-                }
-            },
-            ';' => {
-                if !in_quotes {
-                    let token = assemble_token(&buffer, &res);
-                    buffer = String::new();
-                    res.push(token.expect("Err: Could not lex token"));
-                    res.push(Token::Separator);
-                } else {
-                    buffer.push(c);
-                }
-            },
-            _ => {
-                buffer.push(c);
-            },
-        }
+impl From<PzxError> for Failure {
+    fn from(e: PzxError) -> Failure {
+        Failure::Program(e)
     }
+}
 
-    // In case the command list does not end in a separator
-    if !buffer.is_empty() {
-        let token = assemble_token(&buffer, &res);
-        res.push(token.expect("Err: Could not lex token"));
+// Problems in code that INCLUDE brought in name the file it came from
+fn report(options: &Options, program: Option<&Program>, diagnostics: &[Diagnostic]) {
+    let source = match &options.script {
+        Some(path) => path.display().to_string(),
+        None => "<instructions>".to_owned(),
+    };
+    for diagnostic in diagnostics.iter() {
+        let file = program.and_then(|program| program.file(diagnostic.span)).unwrap_or(&source);
+        eprintln!("{}:{}", file, diagnostic);
     }
+}
 
-    res
+// The program gets the top of the terminal and a pane of the given height the lines below it
+fn split(terminal: &mut NcursesBackend, pane_lines: i32) -> (NcursesBackend, NcursesBackend) {
+    let (lines, cols) = terminal.size();
+    terminal.refresh();
+    let viewport = NcursesBackend::window(lines - pane_lines, cols, 0, 0);
+    let pane = NcursesBackend::window(pane_lines, cols, lines - pane_lines, 0);
+    (viewport, pane)
 }
 
-fn assemble_token(raw: &str, context: &Vec<Token>) -> Option<Token> {
-    let mut res: Option<Token> = None;
+// What shares the terminal with a program
+enum Pane {
+    Debugger(Debugger),
+    Repl(Repl),
+}
 
-    res = match raw {
-        "PRINT" => {
-            Some(Token::Operator(Operator::Print))
-        },
-        "AWAIT" => {
-            Some(Token::Operator(Operator::Await))
-        },
-        "ACCEPT" => {
-            Some(Token::Operator(Operator::Accept))
-        },
-        "FORE" => {
-            Some(Token::Operator(Operator::Foreground))
-        },
-        "BACK" => {
-            Some(Token::Operator(Operator::Background))
-        },
-        "MOVE" => {
-            Some(Token::Operator(Operator::Move))
-        },
-        "SHIFT" => {
-            Some(Token::Operator(Operator::Shift))
-        },
-        "STRING" => {
-            Some(Token::Operator(Operator::StringAssign))
-        },
-        "NUM" => {
-            Some(Token::Operator(Operator::NumberAssign))
-        },
-        "CLR" => {
-            Some(Token::Operator(Operator::ColorAssign))
-        },
-        _ => {
-            None
-        },
+fn run(options: &Options, program: &Program, backend: &mut dyn Backend, trace: Option<Trace>, pane: Option<Pane>) -> Result<String, Failure> {
+    let mut tracing;
+    let inner: &mut dyn Backend = match &trace {
+        Some(trace) => {
+            tracing = TracingBackend {
+                inner: &mut *backend,
+                trace: trace.try_clone().expect("Err: Could not write trace"),
+            };
+            &mut tracing
+        },
+        None => &mut *backend,
     };
 
-    // I could either have deeply-nested case/switch tests or else readable code and these little checks
-    // I chose the latter
-    if res != None {
-        return res;
+    let mut interpreter = Interpreter::new(inner);
+    interpreter.set_args(&options.args);
+    for (name, value) in options.vars.iter() {
+        interpreter.define(name, value);
     }
-    
-    // Check what comes immediately before
-    res = match *context.get(context.len()-1).unwrap() {
-        Token::Operator(Operator::Print) => {
-            // Check first char in raw
-            if raw.chars().next().unwrap() == '\"' {
-                Some(Token::Literal(raw.replace('"', "").to_owned()))
-            } else {
-                Some(Token::Identifier(raw.to_owned()))
-            }
-        },
-        Token::Operator(Operator::Await) => {
-            Some(Token::Identifier(raw.to_owned()))
-        },
-        Token::Operator(Operator::Accept) => {
-            Some(Token::Identifier(raw.to_owned()))
-        },
-        Token::Operator(Operator::Foreground) => {
-            if raw.chars().next().unwrap().is_ascii_digit() {
-                Some(Token::Literal(raw.to_owned()))
-            } else {
-                Some(Token::Identifier(raw.to_owned()))
-            }
-        },
-        Token::Operator(Operator::Background) => {
-            if raw.chars().next().unwrap().is_ascii_digit() {
-                Some(Token::Literal(raw.to_owned()))
-            } else {
-                Some(Token::Identifier(raw.to_owned()))
-            }
-        },
-        Token::Operator(Operator::Move) => {
-            let first_letter = raw.chars().next().unwrap();
-            if first_letter == 'G' {
-                Some(Token::Identifier(raw.to_owned()))
-            } else if first_letter == 'g' {
-                Some(Token::Identifier(raw.to_owned()))
-            } else {
-                Some(Token::Literal(raw.to_owned()))
-            }
-        },
-        Token::Operator(Operator::Shift) => {
-            let first_letter = raw.chars().next().unwrap();
-            if first_letter == 'G' {
-                Some(Token::Identifier(raw.to_owned()))
-            } else if first_letter == 'g' {
-                Some(Token::Identifier(raw.to_owned()))
-            } else {
-                Some(Token::Literal(raw.to_owned()))
-            }
-        },
-        Token::Operator(Operator::StringAssign) => {
-            Some(Token::Identifier(raw.to_owned()))
-        },
-        Token::Operator(Operator::NumberAssign) => {
-            Some(Token::Identifier(raw.to_owned()))
-        },
-        Token::Operator(Operator::ColorAssign) => {
-            Some(Token::Identifier(raw.to_owned()))
-        },
-        _ => {
-            None
-        },
-    };
-
-    // I could either have deeply-nested case/switch tests or else readable code and these little checks
-    // I chose the latter
-    if res != None {
-        return res;
+    if let Some(keys) = &options.keys {
+        interpreter.set_replay(Replay::new(keys.clone(), options.key_delay));
     }
-
-    // Check what comes two lexemes before
-    res = match *context.get(context.len()-2).unwrap() {
-        Token::Operator(Operator::Move) => {
-            let first_letter = raw.chars().next().unwrap();
-            if first_letter == '$' {
-                Some(Token::Identifier(raw.to_owned()))
-            } else {
-                Some(Token::Literal(raw.to_owned()))
-            }
-        },
-        Token::Operator(Operator::Shift) => {
-            let first_letter = raw.chars().next().unwrap();
-            if first_letter == '$' {
-                Some(Token::Identifier(raw.to_owned()))
-            } else {
-                Some(Token::Literal(raw.to_owned()))
-            }
-        },
-        Token::Operator(Operator::StringAssign) => {
-            Some(Token::Literal(raw.to_owned()))
-        },
-        Token::Operator(Operator::NumberAssign) => {
-            Some(Token::Literal(raw.to_owned()))
-        },
-        Token::Operator(Operator::ColorAssign) => {
-            Some(Token::Literal(raw.to_owned()))
-        },
-        _ => {
-            None
-        },
-    };
-
-    // I could either have deeply-nested case/switch tests or else readable code and these little checks
-    // I chose the latter
-    if res != None {
-        return res;
+    if let Some(trace) = trace {
+        interpreter.set_trace(trace);
     }
-
-    res = match *context.get(context.len()-3).unwrap() {
-        Token::Operator(Operator::ColorAssign) => {
-            Some(Token::Literal(raw.to_owned()))
-        },
-        _ => {
-            None
-        }
+    let ret = match pane {
+        Some(Pane::Debugger(debugger)) => {
+            interpreter.set_debugger(debugger);
+            interpreter.run(program)?.out
+        },
+        // The instructions, if any, run before the first entry
+        Some(Pane::Repl(mut repl)) => {
+            interpreter.run(program)?;
+            repl.run(&mut interpreter);
+            interpreter.output()
+        },
+        None => interpreter.run(program)?.out,
     };
+    drop(interpreter);
 
-    // I could either have deeply-nested case/switch tests or else readable code and these little checks
-    // I chose the latter
-    if res != None {
-        return res;
+    if let Some(path) = &options.snapshot_on_exit {
+        if let Err(e) = snapshot::write(&backend.snapshot(), path, Format::from_path(path)) {
+            return Err(Failure::Snapshot(ret, path.to_owned(), e));
+        }
     }
 
-    res = match *context.get(context.len()-4).unwrap() {
-        Token::Operator(Operator::ColorAssign) => {
-            Some(Token::Literal(raw.to_owned()))
-        },
-        _ => {
-            None
-        }
-    };
+    Ok(ret)
+}
 
-    return res;
+#[test]
+fn replay_test() {
+    let options = cli::parse_args(vec!["--headless", "--keys", "yJo<Left>n", "ACCEPT a; AWAIT out"].into_iter().map(|arg| arg.to_owned())).unwrap();
+    let mut screen = VirtualScreen::new(24, 80);
+    // The replayed keys are used even though the screen has keys of its own
+    screen.push_str("ignored\n");
+    // Keys that mean nothing to AWAIT are skipped, and running out of keys ends the input
+    let program = Program::parse(&options.commands).unwrap();
+    assert_eq!(run(&options, &program, &mut screen, None, None).unwrap(), "Jon");
+    assert_eq!(screen.row(0), "yJon");
 }
 
 #[test]
-fn lexer_test() {
-    println!("{:?}", derive_tokens("PRINT \"hi\""));
-    println!("{:?}", derive_tokens("PRINT \"hello world\""));
-    println!("{:?}", derive_tokens("STRING a \"hi\"; PRINT a"));
-    println!("{:?}", derive_tokens("FORE green; BACK bright_red; PRINT \"colors\""));
-    println!("{:?}", derive_tokens("MOVE 0 0"));
-    println!("{:?}", derive_tokens("SHIFT 0 0"));
-    println!("{:?}", derive_tokens("NUM a 0"));
+fn trace_test() {
+    let path = env::temp_dir().join(format!("pzx-trace-test-{}.log", process::id()));
+    let path = path.to_str().unwrap();
+    let mut screen = VirtualScreen::new(24, 80);
+    let program = Program::parse("MOVE 3 10; LET n = y; AWAIT out").unwrap();
+    let options = cli::parse_args(vec!["--keys", "a<Enter>", "AWAIT out"].into_iter().map(|arg| arg.to_owned())).unwrap();
+    run(&options, &program, &mut screen, Some(Trace::create(path, pzx::trace::LogLevel::Info).unwrap()), None).unwrap();
+
+    let log = std::fs::read_to_string(path).unwrap();
+    assert_eq!(log, "1:1 MOVE 3 10 -> x=10 y=3\n1:12 LET n 3 -> n=3\n1:23 AWAIT out -> out=\"a\" x=11\n");
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn snapshot_on_exit_test() {
+    let args = vec!["--snapshot-on-exit", "/no/such/dir/screen.txt", "STRING out \"done\""];
+    let options = cli::parse_args(args.into_iter().map(|arg| arg.to_owned())).unwrap();
+    let program = Program::parse(&options.commands).unwrap();
+    let mut screen = VirtualScreen::new(24, 80);
+    match run(&options, &program, &mut screen, None, None) {
+        Err(Failure::Snapshot(out, path, _)) => assert_eq!((out.as_str(), path.as_str()), ("done", "/no/such/dir/screen.txt")),
+        other => panic!("Expected the snapshot to fail, got {:?}", other),
+    }
 }
@@ -0,0 +1,185 @@
+use crate::backend::{Attribute, Backend, Key};
+
+// Long text shown in the part of the screen from a line down, above a status line. Lines are
+// wrapped to the width of the screen, and the rows they wrap into are what scrolls.
+pub struct Pager {
+    text: String,
+    rows: Vec<String>,
+    top: i32,
+    scroll: usize, // The first row shown
+    search: String,
+    editing: bool, // Whether keys go to the search
+    message: Option<String>, // Shown in the status line until the next key
+}
+
+impl Pager {
+    pub fn new(text: &str, top: i32) -> Pager {
+        Pager {
+            text: text.replace('\t', "    "),
+            rows: Vec::new(),
+            top,
+            scroll: 0,
+            search: String::new(),
+            editing: false,
+            message: None,
+        }
+    }
+
+    // The lines of the region, leaving the last one for the status line
+    pub fn height(&self, backend: &dyn Backend) -> usize {
+        (backend.size().0 - self.top - 1).max(1) as usize
+    }
+
+    // Wraps the text again for the width of the screen, which may have changed
+    fn wrap(&mut self, width: usize) {
+        self.rows.clear();
+        for line in self.text.lines() {
+            let chars: Vec<char> = line.chars().collect();
+            if chars.is_empty() {
+                self.rows.push(String::new());
+            }
+            for chunk in chars.chunks(width.max(1)) {
+                self.rows.push(chunk.iter().collect());
+            }
+        }
+    }
+
+    fn last(&self, height: usize) -> usize {
+        self.rows.len().saturating_sub(height)
+    }
+
+    // Returns true once the user asks to go back to the script
+    pub fn key(&mut self, key: Key, backend: &dyn Backend) -> bool {
+        let height = self.height(backend);
+        self.message = None;
+        if self.editing {
+            match key {
+                Key::Char(c) => self.search.push(c),
+                Key::Backspace => {
+                    self.search.pop();
+                },
+                Key::Enter => {
+                    self.editing = false;
+                    self.find(self.scroll, true);
+                },
+                Key::Escape => {
+                    self.search.clear();
+                    self.editing = false;
+                },
+                _ => {},
+            }
+            return false;
+        }
+
+        match key {
+            Key::Char('q') | Key::Escape => return true,
+            Key::Char('/') => {
+                self.search.clear();
+                self.editing = true;
+            },
+            Key::Char('n') => self.find(self.scroll + 1, true),
+            Key::Char('N') => self.find(self.scroll.saturating_sub(1), false),
+            Key::Down | Key::Enter => self.scroll += 1,
+            Key::Up => self.scroll = self.scroll.saturating_sub(1),
+            Key::PageDown | Key::Char(' ') => self.scroll += height,
+            Key::PageUp => self.scroll = self.scroll.saturating_sub(height),
+            Key::Home => self.scroll = 0,
+            Key::End => self.scroll = self.last(height),
+            _ => {},
+        }
+        self.scroll = self.scroll.min(self.last(height));
+        false
+    }
+
+    // Scrolls to the next row holding the search from a row on, going round the end of the text
+    fn find(&mut self, from: usize, forward: bool) {
+        if self.search.is_empty() || self.rows.is_empty() {
+            return;
+        }
+        let count = self.rows.len();
+        for step in 0..count {
+            let row = if forward { (from + step) % count } else { (from + count - step % count) % count };
+            if self.rows[row].contains(self.search.as_str()) {
+                self.scroll = row;
+                return;
+            }
+        }
+        self.message = Some(format!("\"{}\" not found", self.search));
+    }
+
+    pub fn render(&mut self, backend: &mut dyn Backend) {
+        let width = (backend.size().1 - 1).max(0) as usize;
+        let height = self.height(backend);
+        self.wrap(width);
+        self.scroll = self.scroll.min(self.last(height));
+
+        for i in 0..height {
+            let row = self.rows.get(self.scroll + i).map(|row| row.as_str()).unwrap_or("");
+            backend.move_cursor(self.top + i as i32, 0);
+            self.print_row(backend, row, width);
+        }
+
+        let end = (self.scroll + height).min(self.rows.len());
+        let percent = if self.rows.is_empty() { 100 } else { end * 100 / self.rows.len() };
+        let status = if self.editing {
+            format!(" /{}", self.search)
+        } else {
+            let position = format!(" {}-{} of {} ({}%)", (self.scroll + 1).min(end), end, self.rows.len(), percent);
+            match &self.message {
+                Some(message) => format!("{}  {}", position, message),
+                None => format!("{}  q back  / search  n next  PgUp PgDn scroll", position),
+            }
+        };
+        backend.move_cursor(self.top + height as i32, 0);
+        backend.attribute_on(Attribute::Reverse);
+        backend.print(&format!("{:<1$}", status.chars().take(width).collect::<String>(), width));
+        backend.attribute_off(Attribute::Reverse);
+
+        backend.set_cursor_visible(self.editing);
+        if self.editing {
+            backend.move_cursor(self.top + height as i32, 2 + self.search.chars().count() as i32);
+        }
+        backend.refresh();
+    }
+
+    // Matches of the search are highlighted, and the rest of the line is cleared
+    fn print_row(&self, backend: &mut dyn Backend, row: &str, width: usize) {
+        let mut rest = row;
+        if !self.search.is_empty() && !self.editing {
+            while let Some(start) = rest.find(self.search.as_str()) {
+                backend.print(&rest[..start]);
+                backend.attribute_on(Attribute::Reverse);
+                backend.print(&self.search);
+                backend.attribute_off(Attribute::Reverse);
+                rest = &rest[start + self.search.len()..];
+            }
+        }
+        backend.print(rest);
+        backend.print(&" ".repeat(width.saturating_sub(row.chars().count())));
+    }
+}
+
+#[test]
+fn pager_test() {
+    use crate::backend::VirtualScreen;
+
+    let text = "Terms\n\n1. You may use it.\n2. You may share it with anyone, anywhere, at any time.\n3. That is all.";
+    let mut screen = VirtualScreen::new(4, 20);
+    let mut pager = Pager::new(text, 0);
+    pager.render(&mut screen);
+    assert_eq!(screen.row(2), "1. You may use it.");
+    assert_eq!(screen.row(3).trim_end(), " 1-3 of 7 (42%)  q");
+
+    // The search scrolls to the first match and highlights it
+    for key in [Key::Char('/'), Key::Char('a'), Key::Char('n'), Key::Char('y'), Key::Enter].iter() {
+        assert!(!pager.key(*key, &screen));
+    }
+    pager.render(&mut screen);
+    assert_eq!(screen.row(0), " with anyone, anywh");
+    assert!(screen.cell(0, 6).has(Attribute::Reverse));
+    assert!(!screen.cell(0, 5).has(Attribute::Reverse));
+    pager.key(Key::End, &screen);
+    pager.render(&mut screen);
+    assert_eq!(screen.row(2), "3. That is all.");
+    assert!(pager.key(Key::Char('q'), &screen));
+}
@@ -0,0 +1,1043 @@
+use std::fmt;
+
+use crate::diagnostic::{Diagnostic, Span};
+use crate::error::PzxError;
+use crate::lexer::{derive_tokens, Comparison, Operator, Token};
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum Operand {
+    Literal(String, bool, Span), // Quoted, as in `"80"`, or a number
+    Identifier(String, Span),
+    Call(String, Span), // A host function called without arguments, as in `version()`
+    List(Vec<Operand>, Span), // As in `["a", b]`
+    Index(Box<Operand>, Box<Operand>, Span), // An item of a list, as in `xs[i]`
+    Map(Vec<(String, Operand)>, Span), // As in `{ name: "x", port: 8080 }`
+    Bool(bool, Span),
+    Compare(Box<Operand>, Comparison, Box<Operand>, Span), // As in `x <= 10`
+}
+
+impl Operand {
+    pub fn span(&self) -> Span {
+        match self {
+            Operand::Literal(_, _, span) => *span,
+            Operand::Identifier(_, span) => *span,
+            Operand::Call(_, span) => *span,
+            Operand::List(_, span) => *span,
+            Operand::Index(_, _, span) => *span,
+            Operand::Map(_, span) => *span,
+            Operand::Bool(_, span) => *span,
+            Operand::Compare(_, _, _, span) => *span,
+        }
+    }
+}
+
+// Operands the way they would be written, for traces and dumps
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Literal(s, true, _) => write!(f, "{:?}", s),
+            Operand::Literal(s, false, _) => write!(f, "{}", s),
+            Operand::Identifier(name, _) => write!(f, "{}", name),
+            Operand::Call(name, _) => write!(f, "{}()", name),
+            Operand::List(items, _) => {
+                let items: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+                write!(f, "[{}]", items.join(", "))
+            },
+            Operand::Index(list, index, _) => write!(f, "{}[{}]", list, index),
+            Operand::Map(fields, _) => {
+                let fields: Vec<String> = fields.iter().map(|(key, value)| format!("{}: {}", key, value)).collect();
+                write!(f, "{{{}}}", fields.join(", "))
+            },
+            Operand::Bool(true, _) => write!(f, "TRUE"),
+            Operand::Bool(false, _) => write!(f, "FALSE"),
+            Operand::Compare(left, comparison, right, _) => write!(f, "{} {} {}", left, comparison.symbol(), right),
+        }
+    }
+}
+
+// FORE and BACK take either a color variable or its three components
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum ColorOperand {
+    Variable(Operand),
+    Rgb(Operand, Operand, Operand),
+}
+
+// GAUGE shows either a number or the percentages a command prints, storing its exit status
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum GaugeSource {
+    Value(Operand),
+    Exec(Operand, Option<Operand>),
+}
+
+// TAIL follows either the output of a command or a file
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum TailSource {
+    Exec(Operand),
+    File(Operand),
+}
+
+// What a field of a FORM takes; a choice comes with the list of its options
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum FieldKind {
+    Text,
+    Number,
+    Password,
+    Choice(Operand),
+}
+
+// A field of a FORM, as in `TEXT name "Name" AT 2 4`, and the variable its value goes to
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct Field {
+    pub kind: FieldKind,
+    pub target: Operand,
+    pub label: Operand,
+    pub at: Option<(Operand, Operand)>, // The line and column of the label
+}
+
+// The button that submits a FORM, as in `SUBMIT "Save"`
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct Button {
+    pub label: Operand,
+    pub at: Option<(Operand, Operand)>,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum StatementKind {
+    Print(Operand),
+    Await(Operand),
+    Accept(Operand),
+    Foreground(ColorOperand),
+    Background(ColorOperand),
+    Move(Operand, Operand),
+    Shift(Operand, Operand),
+    StringAssign(Operand, Operand),
+    NumberAssign(Operand, Operand),
+    ColorAssign(Operand, Operand, Operand, Operand),
+    On(Operand, Vec<Statement>),
+    SavePosition(Operand),
+    RestorePosition(Operand),
+    Snapshot(Operand, Option<Operand>),
+    Call(Operand, Vec<Operand>, Option<Operand>), // The function, its arguments and where its result goes
+    Proc(Operand, Vec<Operand>, Vec<Statement>), // The name, the parameters and the body
+    Return(Option<Operand>),
+    Include(Operand),
+    ListAssign(Operand, Operand),
+    Push(Operand, Operand),
+    Pop(Operand, Operand), // The list and where its last item goes
+    For(Operand, Operand, Vec<Statement>), // The variable each item is assigned to, the list and the body
+    MapAssign(Operand, Operand),
+    BoolAssign(Operand, Operand),
+    Confirm(Operand),
+    If(Operand, Vec<Statement>, Vec<Statement>), // The condition, what runs when it holds and what runs otherwise
+    Let(Operand, Operand),
+    Const(Operand, Operand),
+    Set(Operand, Operand),
+    Env(Operand, Operand, Option<Operand>), // The variable, the name in the environment and what to use when it is not set
+    Exec(Operand, Operand), // The variable and the command
+    Shell(Operand, Operand),
+    Spin(Operand, Operand, Option<Operand>), // The label, the command and the variable for its exit status
+    Gauge(Operand, GaugeSource),
+    Tail(Operand, TailSource), // The variable for the lines, and where they come from
+    Pager(Operand, bool), // The text, or the path of a file to read it from when the flag is set
+    Form(Vec<Field>, Option<Button>),
+}
+
+impl StatementKind {
+    // Every operand in the order it was written, not counting the statements of a block
+    pub fn operands(&self) -> Vec<&Operand> {
+        match self {
+            StatementKind::Print(a) | StatementKind::Await(a) | StatementKind::Accept(a) => vec![a],
+            StatementKind::SavePosition(a) | StatementKind::RestorePosition(a) => vec![a],
+            StatementKind::Foreground(color) | StatementKind::Background(color) => match color {
+                ColorOperand::Variable(a) => vec![a],
+                ColorOperand::Rgb(r, g, b) => vec![r, g, b],
+            },
+            StatementKind::Move(a, b) | StatementKind::Shift(a, b) => vec![a, b],
+            StatementKind::StringAssign(a, b) | StatementKind::NumberAssign(a, b) => vec![a, b],
+            StatementKind::ColorAssign(a, r, g, b) => vec![a, r, g, b],
+            StatementKind::On(event, _) => vec![event],
+            StatementKind::Snapshot(path, format) => {
+                let mut res = vec![path];
+                res.extend(format);
+                res
+            },
+            StatementKind::Call(function, arguments, target) => {
+                let mut res = vec![function];
+                res.extend(arguments);
+                res.extend(target);
+                res
+            },
+            StatementKind::Proc(name, parameters, _) => {
+                let mut res = vec![name];
+                res.extend(parameters);
+                res
+            },
+            StatementKind::Return(value) => value.iter().collect(),
+            StatementKind::Include(path) => vec![path],
+            StatementKind::ListAssign(a, b) | StatementKind::Push(a, b) | StatementKind::Pop(a, b) => vec![a, b],
+            StatementKind::MapAssign(a, b) | StatementKind::BoolAssign(a, b) => vec![a, b],
+            StatementKind::Let(a, b) | StatementKind::Const(a, b) | StatementKind::Set(a, b) => vec![a, b],
+            StatementKind::Exec(a, b) | StatementKind::Shell(a, b) => vec![a, b],
+            StatementKind::Spin(label, command, target) | StatementKind::Gauge(label, GaugeSource::Exec(command, target)) => {
+                let mut res = vec![label, command];
+                res.extend(target);
+                res
+            },
+            StatementKind::Gauge(label, GaugeSource::Value(value)) => vec![label, value],
+            StatementKind::Tail(target, TailSource::Exec(source)) | StatementKind::Tail(target, TailSource::File(source)) => vec![target, source],
+            StatementKind::Pager(text, _) => vec![text],
+            StatementKind::Form(fields, button) => {
+                let mut res = Vec::new();
+                for field in fields {
+                    res.push(&field.target);
+                    res.push(&field.label);
+                    if let FieldKind::Choice(options) = &field.kind {
+                        res.push(options);
+                    }
+                    if let Some((line, column)) = &field.at {
+                        res.extend([line, column]);
+                    }
+                }
+                if let Some(button) = button {
+                    res.push(&button.label);
+                    if let Some((line, column)) = &button.at {
+                        res.extend([line, column]);
+                    }
+                }
+                res
+            },
+            StatementKind::Env(target, variable, default) => {
+                let mut res = vec![target, variable];
+                res.extend(default);
+                res
+            },
+            StatementKind::Confirm(target) => vec![target],
+            StatementKind::If(condition, _, _) => vec![condition],
+            StatementKind::For(item, list, _) => vec![item, list],
+        }
+    }
+
+    // The statements of a block, which run later rather than in sequence. For IF, these are the
+    // statements that run when its condition holds.
+    pub fn body(&self) -> Option<&[Statement]> {
+        match self {
+            StatementKind::On(_, body) | StatementKind::Proc(_, _, body) | StatementKind::For(_, _, body) => Some(body),
+            StatementKind::If(_, body, _) => Some(body),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct Statement {
+    pub operator: Operator,
+    pub kind: StatementKind,
+    pub span: Span, // The span of the operator
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Default)]
+pub struct Program {
+    pub statements: Vec<Statement>,
+    pub files: Vec<String>, // The files INCLUDE brought in, in the order spans number them
+}
+
+impl Program {
+    // Lexes and parses instructions in one go
+    pub fn parse(raw: &str) -> Result<Program, PzxError> {
+        let tokens = derive_tokens(raw).map_err(|e| PzxError::Parse(vec![e]))?;
+        parse_tokens(&tokens).map_err(PzxError::Parse)
+    }
+
+    // The included file a span was found in, or None for the instructions themselves
+    pub fn file(&self, span: Span) -> Option<&str> {
+        self.files.get(span.file.checked_sub(1)?).map(String::as_str)
+    }
+}
+
+// Groups tokens into statements. Every problem found is reported rather than only the first:
+// after an error the parser skips ahead to the next statement and carries on.
+pub fn parse_tokens(tokens: &[(Token, Span)]) -> Result<Program, Vec<Diagnostic>> {
+    Parser::new(tokens).parse()
+}
+
+// The words that start a field of a FORM
+const FIELDS: [&str; 5] = ["TEXT", "NUMBER", "PASSWORD", "CHOICE", "SUBMIT"];
+
+pub struct Parser<'a> {
+    tokens: &'a [(Token, Span)],
+    position: usize,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(tokens: &'a [(Token, Span)]) -> Parser<'a> {
+        Parser {
+            tokens,
+            position: 0,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    pub fn parse(mut self) -> Result<Program, Vec<Diagnostic>> {
+        let statements = self.statements(None);
+        if self.diagnostics.is_empty() {
+            Ok(Program { statements, files: Vec::new() })
+        } else {
+            Err(self.diagnostics)
+        }
+    }
+
+    fn peek(&self) -> Option<&'a (Token, Span)> {
+        self.tokens.get(self.position)
+    }
+
+    // Statements up to the END of the block opened at `opener`, or up to the end of the program
+    fn statements(&mut self, opener: Option<Span>) -> Vec<Statement> {
+        self.block(opener, false).0
+    }
+
+    // The same, but where an ELSE may also end the block; whether one did is returned as well
+    fn block(&mut self, opener: Option<Span>, allow_else: bool) -> (Vec<Statement>, bool) {
+        let mut res: Vec<Statement> = Vec::new();
+
+        loop {
+            let (token, span) = match self.peek() {
+                Some(next) => next,
+                None => {
+                    if let Some(opener) = opener {
+                        self.diagnostics.push(Diagnostic::error(opener, "Block is never closed with END".to_owned()));
+                    }
+                    return (res, false);
+                },
+            };
+
+            match token {
+                Token::Separator => {
+                    self.position += 1;
+                },
+                Token::Operator(Operator::End) => {
+                    self.position += 1;
+                    if opener.is_some() {
+                        return (res, false);
+                    }
+                    self.diagnostics.push(Diagnostic::error(*span, "END provided without a matching ON, PROC, FOR, IF or FORM".to_owned()));
+                },
+                Token::Operator(Operator::Else) if allow_else => {
+                    self.position += 1;
+                    return (res, true);
+                },
+                Token::Operator(operator) => {
+                    self.position += 1;
+                    match self.statement(*operator, *span) {
+                        Ok(statement) => res.push(statement),
+                        Err(e) => {
+                            self.diagnostics.push(e);
+                            self.recover();
+                        },
+                    }
+                },
+                _ => {
+                    self.diagnostics.push(Diagnostic::error(*span, format!("Expected an operator, found `{}`", token_text(token))));
+                    self.recover();
+                },
+            }
+        }
+    }
+
+    // Skips the rest of a broken statement
+    fn recover(&mut self) {
+        while let Some((token, _)) = self.peek() {
+            match token {
+                Token::Separator | Token::Operator(_) => return,
+                _ => self.position += 1,
+            }
+        }
+    }
+
+    fn statement(&mut self, operator: Operator, span: Span) -> Result<Statement, Diagnostic> {
+        let name = operator.name();
+        let kind = match operator {
+            Operator::Print => StatementKind::Print(self.value(name, span)?),
+            Operator::Await => StatementKind::Await(self.variable(name, span)?),
+            Operator::Accept => StatementKind::Accept(self.variable(name, span)?),
+            Operator::Foreground => StatementKind::Foreground(self.color(name, span)?),
+            Operator::Background => StatementKind::Background(self.color(name, span)?),
+            Operator::Move => StatementKind::Move(self.value(name, span)?, self.value(name, span)?),
+            Operator::Shift => StatementKind::Shift(self.value(name, span)?, self.value(name, span)?),
+            Operator::StringAssign => StatementKind::StringAssign(self.variable(name, span)?, self.value(name, span)?),
+            Operator::NumberAssign => StatementKind::NumberAssign(self.variable(name, span)?, self.value(name, span)?),
+            Operator::ColorAssign => StatementKind::ColorAssign(
+                self.variable(name, span)?,
+                self.literal(name, span)?,
+                self.literal(name, span)?,
+                self.literal(name, span)?,
+            ),
+            Operator::On => {
+                let event = self.variable(name, span)?;
+                let body = self.statements(Some(span));
+                return Ok(Statement { operator, kind: StatementKind::On(event, body), span });
+            },
+            Operator::SavePosition => StatementKind::SavePosition(self.variable(name, span)?),
+            Operator::RestorePosition => StatementKind::RestorePosition(self.variable(name, span)?),
+            Operator::Snapshot => {
+                let path = self.value(name, span)?;
+                let format = if let Some(format_span) = self.keyword("FORMAT") {
+                    self.position += 1;
+                    Some(self.variable("FORMAT", format_span)?)
+                } else {
+                    None
+                };
+                StatementKind::Snapshot(path, format)
+            },
+            Operator::Call => {
+                let function = self.variable(name, span)?;
+                let mut arguments = Vec::new();
+                // A variable named INTO can still be passed, as long as no operand follows it
+                while self.at_value() && !(self.keyword("INTO").is_some() && self.value_follows()) {
+                    arguments.push(self.value(name, span)?);
+                }
+                StatementKind::Call(function, arguments, self.into()?)
+            },
+            Operator::Proc => {
+                let procedure = self.variable(name, span)?;
+                let mut parameters = Vec::new();
+                while self.at_value() {
+                    parameters.push(self.variable(name, span)?);
+                }
+                let body = self.statements(Some(span));
+                return Ok(Statement { operator, kind: StatementKind::Proc(procedure, parameters, body), span });
+            },
+            Operator::Return => {
+                let value = if self.at_value() {
+                    Some(self.value(name, span)?)
+                } else {
+                    None
+                };
+                StatementKind::Return(value)
+            },
+            Operator::Include => StatementKind::Include(self.literal(name, span)?),
+            Operator::ListAssign => StatementKind::ListAssign(self.variable(name, span)?, self.value(name, span)?),
+            Operator::MapAssign => StatementKind::MapAssign(self.variable(name, span)?, self.value(name, span)?),
+            Operator::BoolAssign => StatementKind::BoolAssign(self.variable(name, span)?, self.value(name, span)?),
+            Operator::Confirm => StatementKind::Confirm(self.variable(name, span)?),
+            Operator::Exec => StatementKind::Exec(self.variable(name, span)?, self.value(name, span)?),
+            Operator::Shell => StatementKind::Shell(self.variable(name, span)?, self.value(name, span)?),
+            Operator::Spin => {
+                let label = self.value(name, span)?;
+                let command = self.exec(name, span)?;
+                StatementKind::Spin(label, command, self.into()?)
+            },
+            Operator::Gauge => {
+                let label = self.value(name, span)?;
+                let source = match self.peek() {
+                    Some((Token::Operator(Operator::Exec), _)) => {
+                        let command = self.exec(name, span)?;
+                        GaugeSource::Exec(command, self.into()?)
+                    },
+                    _ => GaugeSource::Value(self.value(name, span)?),
+                };
+                StatementKind::Gauge(label, source)
+            },
+            Operator::If => {
+                let condition = self.value(name, span)?;
+                let (body, has_else) = self.block(Some(span), true);
+                let otherwise = if has_else { self.statements(Some(span)) } else { Vec::new() };
+                return Ok(Statement { operator, kind: StatementKind::If(condition, body, otherwise), span });
+            },
+            Operator::Else => {
+                return Err(Diagnostic::error(span, "ELSE provided without a matching IF".to_owned()));
+            },
+            Operator::Let => {
+                let (target, value) = self.binding(name, span)?;
+                StatementKind::Let(target, value)
+            },
+            Operator::Const => {
+                let (target, value) = self.binding(name, span)?;
+                StatementKind::Const(target, value)
+            },
+            Operator::Set => {
+                let (target, value) = self.binding(name, span)?;
+                StatementKind::Set(target, value)
+            },
+            Operator::Env => {
+                let target = self.variable(name, span)?;
+                let variable = self.value(name, span)?;
+                let default = if let Some(default_span) = self.keyword("DEFAULT") {
+                    self.position += 1;
+                    Some(self.value("DEFAULT", default_span)?)
+                } else {
+                    None
+                };
+                StatementKind::Env(target, variable, default)
+            },
+            Operator::Tail => {
+                let target = self.variable(name, span)?;
+                let source = match self.keyword("FROM") {
+                    Some(_) => {
+                        self.position += 1;
+                        match self.peek() {
+                            Some((Token::Identifier(file), file_span)) if file == "FILE" => {
+                                let file_span = *file_span;
+                                self.position += 1;
+                                TailSource::File(self.value("FILE", file_span)?)
+                            },
+                            Some((token, extra)) if *token != Token::Separator => {
+                                return Err(Diagnostic::error(*extra, format!("Expected FILE after FROM, found `{}`", token_text(token))));
+                            },
+                            _ => return Err(self.missing(name, span)),
+                        }
+                    },
+                    None => TailSource::Exec(self.exec(name, span)?),
+                };
+                StatementKind::Tail(target, source)
+            },
+            // A quoted path names a file, while anything else holds the text, unless FILE says
+            // it holds the path. `PAGER FILE` on its own shows a variable named FILE.
+            Operator::Pager => match self.keyword("FILE") {
+                Some(file_span) if self.value_follows() => {
+                    self.position += 1;
+                    StatementKind::Pager(self.value("FILE", file_span)?, true)
+                },
+                _ => {
+                    let text = self.value(name, span)?;
+                    let file = matches!(text, Operand::Literal(_, true, _));
+                    StatementKind::Pager(text, file)
+                },
+            },
+            Operator::Form => {
+                let (fields, button) = self.form(span);
+                return Ok(Statement { operator, kind: StatementKind::Form(fields, button), span });
+            },
+            Operator::Push => StatementKind::Push(self.variable(name, span)?, self.value(name, span)?),
+            Operator::Pop => StatementKind::Pop(self.variable(name, span)?, self.variable(name, span)?),
+            Operator::For => {
+                let item = self.variable(name, span)?;
+                match self.peek() {
+                    Some((Token::Identifier(word), _)) if word == "IN" => self.position += 1,
+                    Some((token, extra)) => {
+                        return Err(Diagnostic::error(*extra, format!("Expected IN after the variable of FOR, found `{}`", token_text(token))));
+                    },
+                    None => return Err(self.missing(name, span)),
+                }
+                let list = self.value(name, span)?;
+                let body = self.statements(Some(span));
+                return Ok(Statement { operator, kind: StatementKind::For(item, list, body), span });
+            },
+            Operator::End => {
+                return Err(Diagnostic::error(span, "END provided without a matching ON, PROC, FOR, IF or FORM".to_owned()));
+            },
+        };
+
+        self.finished(name)?;
+        Ok(Statement { operator, kind, span })
+    }
+
+    // Whatever follows the operands has to start the next statement
+    fn finished(&self, name: &str) -> Result<(), Diagnostic> {
+        match self.peek() {
+            Some((Token::Separator, _)) | Some((Token::Operator(_), _)) | None => Ok(()),
+            Some((token, extra)) => {
+                Err(Diagnostic::error(*extra, format!("Too many operands provided to {} operator: `{}`", name, token_text(token))))
+            },
+        }
+    }
+
+    // Fields may follow each other without a separator, like statements do
+    fn field_finished(&self, name: &str) -> Result<(), Diagnostic> {
+        if self.at_field() {
+            return Ok(());
+        }
+        self.finished(name)
+    }
+
+    fn at_field(&self) -> bool {
+        FIELDS.iter().any(|word| self.keyword(word).is_some())
+    }
+
+    // Skips the rest of a broken field, up to the next one
+    fn recover_field(&mut self) {
+        while !self.at_field() {
+            match self.peek() {
+                Some((Token::Separator, _)) | Some((Token::Operator(_), _)) | None => return,
+                _ => self.position += 1,
+            }
+        }
+    }
+
+    // The fields of a FORM up to its END. Like the statements of a block, a broken field is
+    // reported and skipped.
+    fn form(&mut self, opener: Span) -> (Vec<Field>, Option<Button>) {
+        let mut fields: Vec<Field> = Vec::new();
+        let mut button = None;
+
+        loop {
+            let (token, span) = match self.peek() {
+                Some((Token::Separator, _)) => {
+                    self.position += 1;
+                    continue;
+                },
+                Some((Token::Operator(Operator::End), _)) => {
+                    self.position += 1;
+                    return (fields, button);
+                },
+                Some((token, span)) => (token, *span),
+                None => {
+                    self.diagnostics.push(Diagnostic::error(opener, "Block is never closed with END".to_owned()));
+                    return (fields, button);
+                },
+            };
+            self.position += 1;
+
+            let word = match token {
+                Token::Identifier(word) => word.as_str(),
+                _ => "",
+            };
+            let res = match word {
+                "SUBMIT" if button.is_some() => Err(Diagnostic::error(span, "FORM has more than one SUBMIT".to_owned())),
+                "SUBMIT" => self.button(span).map(|submit| button = Some(submit)),
+                "TEXT" | "NUMBER" | "PASSWORD" | "CHOICE" => {
+                    self.field(word, span).and_then(|field| {
+                        if fields.iter().any(|other| other.target.to_string() == field.target.to_string()) {
+                            return Err(Diagnostic::error(field.target.span(), format!("`{}` has more than one field in FORM", field.target)));
+                        }
+                        fields.push(field);
+                        Ok(())
+                    })
+                },
+                _ => Err(Diagnostic::error(span, format!("Expected TEXT, NUMBER, PASSWORD, CHOICE or SUBMIT in FORM, found `{}`", token_text(token)))),
+            };
+            if let Err(e) = res {
+                self.diagnostics.push(e);
+                self.recover_field();
+            }
+        }
+    }
+
+    // `TEXT name "Label"`, with the options of a CHOICE after its label, and maybe `AT line column`
+    fn field(&mut self, name: &str, span: Span) -> Result<Field, Diagnostic> {
+        let target = self.variable(name, span)?;
+        let label = self.value(name, span)?;
+        let kind = match name {
+            "NUMBER" => FieldKind::Number,
+            "PASSWORD" => FieldKind::Password,
+            "CHOICE" => FieldKind::Choice(self.value(name, span)?),
+            _ => FieldKind::Text,
+        };
+        let at = self.at()?;
+        self.field_finished(name)?;
+        Ok(Field { kind, target, label, at })
+    }
+
+    fn button(&mut self, span: Span) -> Result<Button, Diagnostic> {
+        let label = self.value("SUBMIT", span)?;
+        let at = self.at()?;
+        self.field_finished("SUBMIT")?;
+        Ok(Button { label, at })
+    }
+
+    fn at(&mut self) -> Result<Option<(Operand, Operand)>, Diagnostic> {
+        match self.keyword("AT") {
+            Some(at_span) => {
+                self.position += 1;
+                Ok(Some((self.value("AT", at_span)?, self.value("AT", at_span)?)))
+            },
+            None => Ok(None),
+        }
+    }
+
+    // `INTO name`, where CALL, SPIN and GAUGE may store what they produced
+    fn into(&mut self) -> Result<Option<Operand>, Diagnostic> {
+        match self.keyword("INTO") {
+            Some(into_span) => {
+                self.position += 1;
+                Ok(Some(self.variable("INTO", into_span)?))
+            },
+            None => Ok(None),
+        }
+    }
+
+    // `EXEC command`, as SPIN and GAUGE take it
+    fn exec(&mut self, name: &str, span: Span) -> Result<Operand, Diagnostic> {
+        match self.peek() {
+            Some((Token::Operator(Operator::Exec), exec_span)) => {
+                let exec_span = *exec_span;
+                self.position += 1;
+                self.value("EXEC", exec_span)
+            },
+            Some((token, extra)) if *token != Token::Separator => {
+                Err(Diagnostic::error(*extra, format!("Expected EXEC after the first operand of {}, found `{}`", name, token_text(token))))
+            },
+            _ => Err(self.missing(name, span)),
+        }
+    }
+
+    // `name = value`, as LET, CONST and SET take it
+    fn binding(&mut self, name: &str, span: Span) -> Result<(Operand, Operand), Diagnostic> {
+        let target = self.variable(name, span)?;
+        match self.peek() {
+            Some((Token::Punctuation('='), _)) => self.position += 1,
+            Some((token, extra)) if *token != Token::Separator => {
+                return Err(Diagnostic::error(*extra, format!("Expected `=` after `{}`, found `{}`", target, token_text(token))));
+            },
+            _ => return Err(self.missing(name, span)),
+        }
+        Ok((target, self.value(name, span)?))
+    }
+
+    fn at_value(&self) -> bool {
+        self.peek().is_some_and(|(token, _)| starts_value(token))
+    }
+
+    // Whether the token after the next one starts a value
+    fn value_follows(&self) -> bool {
+        self.tokens.get(self.position + 1).is_some_and(|(token, _)| starts_value(token))
+    }
+
+    // Words such as INTO or FROM that join the operands of a statement are identifiers, so they
+    // are only taken as keywords where the statement expects them
+    fn keyword(&self, word: &str) -> Option<Span> {
+        match self.peek() {
+            Some((Token::Identifier(s), span)) if s == word => Some(*span),
+            _ => None,
+        }
+    }
+
+    // A value that may be compared with another one, as in `x <= 10`
+    fn value(&mut self, name: &str, span: Span) -> Result<Operand, Diagnostic> {
+        let left = self.indexed(name, span)?;
+        if let Some((Token::Comparison(comparison), _)) = self.peek() {
+            self.position += 1;
+            let right = self.indexed(name, span)?;
+            let whole = Span { end: right.span().end, ..left.span() };
+            return Ok(Operand::Compare(Box::new(left), *comparison, Box::new(right), whole));
+        }
+        Ok(left)
+    }
+
+    // A literal, a variable, a call or a list, any of which may be indexed
+    fn indexed(&mut self, name: &str, span: Span) -> Result<Operand, Diagnostic> {
+        let mut res = self.item(name, span)?;
+
+        // Brackets only index what they directly follow; `a [1]` is a value and then a list
+        while let Some((Token::Punctuation('['), open)) = self.peek() {
+            if open.start != res.span().end {
+                break;
+            }
+            self.position += 1;
+            let index = self.value(name, span)?;
+            let close = self.close(*open)?;
+            let whole = Span { end: close.end, ..res.span() };
+            res = Operand::Index(Box::new(res), Box::new(index), whole);
+        }
+
+        Ok(res)
+    }
+
+    fn item(&mut self, name: &str, span: Span) -> Result<Operand, Diagnostic> {
+        match self.peek() {
+            Some((Token::Punctuation('['), open)) => {
+                self.position += 1;
+                self.list(name, *open)
+            },
+            Some((Token::Punctuation('{'), open)) => {
+                self.position += 1;
+                self.map(name, *open)
+            },
+            Some((Token::Identifier(s), operand_span)) if s == "TRUE" || s == "FALSE" => {
+                self.position += 1;
+                Ok(Operand::Bool(s == "TRUE", *operand_span))
+            },
+            // A number has to fit, rather than becoming a string when it does not
+            Some((Token::Literal(s, false), operand_span)) if s.parse::<i32>().is_err() => {
+                let unsigned = s.strip_prefix('-').unwrap_or(s);
+                let problem = if unsigned.bytes().all(|b| b.is_ascii_digit()) { "is too large for a number" } else { "is not a number" };
+                Err(Diagnostic::error(*operand_span, format!("`{}` {}", s, problem)))
+            },
+            Some((Token::Literal(s, quoted), operand_span)) => {
+                self.position += 1;
+                Ok(Operand::Literal(s.to_owned(), *quoted, *operand_span))
+            },
+            Some((Token::Identifier(s), operand_span)) => {
+                self.position += 1;
+                Ok(Operand::Identifier(s.to_owned(), *operand_span))
+            },
+            Some((Token::Call(s), operand_span)) => {
+                self.position += 1;
+                Ok(Operand::Call(s.to_owned(), *operand_span))
+            },
+            _ => Err(self.missing(name, span)),
+        }
+    }
+
+    // The items of a list literal, once its `[` has been read
+    fn list(&mut self, name: &str, open: Span) -> Result<Operand, Diagnostic> {
+        let mut items = Vec::new();
+        loop {
+            if let Some((Token::Punctuation(']'), _)) = self.peek() {
+                let close = self.close(open)?;
+                return Ok(Operand::List(items, Span { end: close.end, ..open }));
+            }
+
+            items.push(self.value(name, open)?);
+            match self.peek() {
+                Some((Token::Punctuation(','), _)) => self.position += 1,
+                Some((Token::Punctuation(']'), _)) => {},
+                Some((token, extra)) if *token != Token::Separator => {
+                    return Err(Diagnostic::error(*extra, format!("Expected `,` or `]` in list, found `{}`", token_text(token))));
+                },
+                _ => return Err(Diagnostic::error(open, "`[` is never closed with `]`".to_owned())),
+            }
+        }
+    }
+
+    // The fields of a map literal, once its `{` has been read. Keys are written as names or in
+    // quotes.
+    fn map(&mut self, name: &str, open: Span) -> Result<Operand, Diagnostic> {
+        let never_closed = || Diagnostic::error(open, "`{` is never closed with `}`".to_owned());
+        let mut fields: Vec<(String, Operand)> = Vec::new();
+        loop {
+            let key = match self.peek() {
+                Some((Token::Punctuation('}'), close)) => {
+                    self.position += 1;
+                    return Ok(Operand::Map(fields, Span { end: close.end, ..open }));
+                },
+                Some((Token::Identifier(key), key_span)) | Some((Token::Literal(key, _), key_span)) => {
+                    if fields.iter().any(|(existing, _)| existing == key) {
+                        return Err(Diagnostic::error(*key_span, format!("Key `{}` appears twice in map", key)));
+                    }
+                    self.position += 1;
+                    key.to_owned()
+                },
+                Some((Token::Separator, _)) | None => return Err(never_closed()),
+                Some((token, extra)) => {
+                    return Err(Diagnostic::error(*extra, format!("Expected a key in map, found `{}`", token_text(token))));
+                },
+            };
+
+            match self.peek() {
+                Some((Token::Punctuation(':'), _)) => self.position += 1,
+                Some((Token::Separator, _)) | None => return Err(never_closed()),
+                Some((token, extra)) => {
+                    return Err(Diagnostic::error(*extra, format!("Expected `:` after `{}` in map, found `{}`", key, token_text(token))));
+                },
+            }
+            fields.push((key, self.value(name, open)?));
+
+            match self.peek() {
+                Some((Token::Punctuation(','), _)) => self.position += 1,
+                Some((Token::Punctuation('}'), _)) => {},
+                Some((token, extra)) if *token != Token::Separator => {
+                    return Err(Diagnostic::error(*extra, format!("Expected `,` or `}}` in map, found `{}`", token_text(token))));
+                },
+                _ => return Err(never_closed()),
+            }
+        }
+    }
+
+    fn close(&mut self, open: Span) -> Result<Span, Diagnostic> {
+        match self.peek() {
+            Some((Token::Punctuation(']'), close)) => {
+                self.position += 1;
+                Ok(*close)
+            },
+            _ => Err(Diagnostic::error(open, "`[` is never closed with `]`".to_owned())),
+        }
+    }
+
+    // TRUE and FALSE cannot name a variable, since they would be read as values
+    fn variable(&mut self, name: &str, span: Span) -> Result<Operand, Diagnostic> {
+        match self.peek() {
+            Some((Token::Identifier(s), operand_span)) if s == "TRUE" || s == "FALSE" => {
+                Err(Diagnostic::error(*operand_span, format!("Improper operand provided to {} operator: expected a variable, found `{}`", name, s)))
+            },
+            Some((Token::Identifier(s), operand_span)) => {
+                self.position += 1;
+                Ok(Operand::Identifier(s.to_owned(), *operand_span))
+            },
+            Some((Token::Literal(s, _), operand_span)) => {
+                Err(Diagnostic::error(*operand_span, format!("Improper operand provided to {} operator: expected a variable, found `{}`", name, s)))
+            },
+            Some((Token::Call(s), operand_span)) => {
+                Err(Diagnostic::error(*operand_span, format!("Improper operand provided to {} operator: expected a variable, found `{}()`", name, s)))
+            },
+            _ => Err(self.missing(name, span)),
+        }
+    }
+
+    fn literal(&mut self, name: &str, span: Span) -> Result<Operand, Diagnostic> {
+        match self.peek() {
+            Some((Token::Literal(s, quoted), operand_span)) => {
+                self.position += 1;
+                Ok(Operand::Literal(s.to_owned(), *quoted, *operand_span))
+            },
+            Some((Token::Identifier(s), operand_span)) | Some((Token::Call(s), operand_span)) => {
+                Err(Diagnostic::error(*operand_span, format!("Improper operand provided to {} operator: expected a literal, found `{}`", name, s)))
+            },
+            _ => Err(self.missing(name, span)),
+        }
+    }
+
+    fn color(&mut self, name: &str, span: Span) -> Result<ColorOperand, Diagnostic> {
+        if let Some((Token::Literal(..), _)) = self.peek() {
+            Ok(ColorOperand::Rgb(self.literal(name, span)?, self.literal(name, span)?, self.literal(name, span)?))
+        } else {
+            Ok(ColorOperand::Variable(self.variable(name, span)?))
+        }
+    }
+
+    fn missing(&self, name: &str, span: Span) -> Diagnostic {
+        Diagnostic::error(span, format!("Missing operand for {} operator", name))
+    }
+}
+
+// Literals, variables, calls, lists and maps
+fn starts_value(token: &Token) -> bool {
+    matches!(token, Token::Literal(..) | Token::Identifier(_) | Token::Call(_) | Token::Punctuation('[') | Token::Punctuation('{'))
+}
+
+// A token the way it was written, for diagnostics
+fn token_text(token: &Token) -> String {
+    match token {
+        Token::Operator(operator) => operator.name().to_owned(),
+        Token::Separator => ";".to_owned(),
+        Token::Identifier(s) | Token::Literal(s, _) => s.to_owned(),
+        Token::Call(s) => format!("{}()", s),
+        Token::Punctuation(c) => c.to_string(),
+        Token::Comparison(comparison) => comparison.symbol().to_owned(),
+    }
+}
+
+#[cfg(test)]
+fn parse(raw: &str) -> Result<Program, Vec<Diagnostic>> {
+    parse_tokens(&crate::lexer::derive_tokens(raw).unwrap())
+}
+
+#[test]
+fn parser_test() {
+    let program = parse("ON RESIZE; MOVE G 0; END; FORE 0 0 1000; SNAPSHOT \"a.html\" FORMAT html").unwrap();
+    assert_eq!(program.statements.len(), 3);
+    if let StatementKind::On(_, body) = &program.statements[0].kind {
+        assert_eq!(body.len(), 1);
+        assert_eq!(body[0].operator, Operator::Move);
+    } else {
+        panic!("Expected an ON block");
+    }
+    assert!(matches!(program.statements[1].kind, StatementKind::Foreground(ColorOperand::Rgb(..))));
+    assert!(matches!(program.statements[2].kind, StatementKind::Snapshot(_, Some(_))));
+}
+
+#[test]
+fn parser_error_test() {
+    let errors = parse("MOVE 1;\nPRINT a b; AWAIT \"x\"; END; ON RESIZE").unwrap_err();
+    let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+    assert_eq!(messages, vec![
+        "1:1: error: Missing operand for MOVE operator",
+        "2:9: error: Too many operands provided to PRINT operator: `b`",
+        "2:18: error: Improper operand provided to AWAIT operator: expected a variable, found `x`",
+        "2:23: error: END provided without a matching ON, PROC, FOR, IF or FORM",
+        "2:28: error: Block is never closed with END",
+    ]);
+}
+
+#[test]
+fn list_parser_test() {
+    let program = parse("PRINT xs[i][0]; LIST ys [1, [a], ]").unwrap();
+    let operands: Vec<String> = program.statements.iter().flat_map(|statement| statement.kind.operands()).map(|operand| operand.to_string()).collect();
+    assert_eq!(operands, vec!["xs[i][0]", "ys", "[1, [a]]"]);
+
+    let errors = parse("PRINT xs [0]; LIST ys [1 2]; FOR x xs; END; LIST zs [1").unwrap_err();
+    let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+    assert_eq!(messages, vec![
+        "1:10: error: Too many operands provided to PRINT operator: `[`",
+        "1:26: error: Expected `,` or `]` in list, found `2`",
+        "1:36: error: Expected IN after the variable of FOR, found `xs`",
+        "1:40: error: END provided without a matching ON, PROC, FOR, IF or FORM",
+        "1:53: error: `[` is never closed with `]`",
+    ]);
+}
+
+#[test]
+fn map_parser_test() {
+    let program = parse("MAP cfg { name: \"x\", \"port\":8080, nested: {}, }").unwrap();
+    assert_eq!(program.statements[0].kind.operands()[1].to_string(), "{name: \"x\", port: 8080, nested: {}}");
+
+    let errors = parse("MAP a { b 1 }; MAP a { b: 1, b: 2 }; MAP a { b: 1").unwrap_err();
+    let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+    assert_eq!(messages, vec![
+        "1:11: error: Expected `:` after `b` in map, found `1`",
+        "1:30: error: Key `b` appears twice in map",
+        "1:44: error: `{` is never closed with `}`",
+    ]);
+}
+
+#[test]
+fn if_parser_test() {
+    let program = parse("IF x >= 2; PRINT \"a\"; ELSE; PRINT \"b\"; PRINT \"c\"; END; BOOL t TRUE").unwrap();
+    match &program.statements[0].kind {
+        StatementKind::If(condition, body, otherwise) => {
+            assert_eq!(condition.to_string(), "x >= 2");
+            assert_eq!((body.len(), otherwise.len()), (1, 2));
+        },
+        _ => panic!("Err: Expected IF"),
+    }
+    assert_eq!(program.statements[1].kind.operands()[1].to_string(), "TRUE");
+
+    let errors = parse("ELSE; FOR x IN xs; ELSE; END").unwrap_err();
+    let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+    assert_eq!(messages, vec!["1:1: error: ELSE provided without a matching IF", "1:20: error: ELSE provided without a matching IF"]);
+}
+
+#[test]
+fn binding_parser_test() {
+    let program = parse("LET xs = [1, 2]; CONST size = xs[0] > 1; SET xs = []").unwrap();
+    let operands: Vec<String> = program.statements.iter().map(|statement| statement.kind.operands()[1].to_string()).collect();
+    assert_eq!(operands, vec!["[1, 2]", "xs[0] > 1", "[]"]);
+
+    let errors = parse("LET x 1; SET y; LET n = 99999999999; LET m = -1x").unwrap_err();
+    let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+    assert_eq!(messages, vec![
+        "1:7: error: Expected `=` after `x`, found `1`",
+        "1:10: error: Missing operand for SET operator",
+        "1:25: error: `99999999999` is too large for a number",
+        "1:46: error: `-1x` is not a number",
+    ]);
+}
+
+#[test]
+fn form_parser_test() {
+    let program = parse("FORM\n  TEXT name \"Name\"\n  CHOICE color \"Color\" [\"red\", \"blue\"] AT 4 2\n  SUBMIT \"Save\"\nEND").unwrap();
+    let operands: Vec<String> = program.statements[0].kind.operands().iter().map(|operand| operand.to_string()).collect();
+    assert_eq!(operands, vec!["name", "\"Name\"", "color", "\"Color\"", "[\"red\", \"blue\"]", "4", "2", "\"Save\""]);
+
+    let errors = parse("FORM TEXT a \"A\"; NUMBER a \"B\"; TEXT c \"C\" AT 1; PRINT \"x\"; SUBMIT \"OK\"; SUBMIT \"OK\" END; TEXT b \"B\"").unwrap_err();
+    let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+    assert_eq!(messages, vec![
+        "1:25: error: `a` has more than one field in FORM",
+        "1:43: error: Missing operand for AT operator",
+        "1:49: error: Expected TEXT, NUMBER, PASSWORD, CHOICE or SUBMIT in FORM, found `PRINT`",
+        "1:73: error: FORM has more than one SUBMIT",
+        "1:90: error: Expected an operator, found `TEXT`",
+    ]);
+}
+
+#[test]
+fn keyword_parser_test() {
+    // The words that join operands are keywords only where a statement expects them
+    let program = parse("LET file = 1; NUM FILE 2; LIST IN [1]; FOR at IN IN; PRINT at; END; CALL f INTO;
+CALL f DEFAULT INTO INTO; PAGER FILE; PAGER FILE text; MAP m { TRUE: 1 }; SNAPSHOT FORMAT FORMAT html").unwrap();
+    let operands: Vec<String> = program.statements.iter().flat_map(|statement| statement.kind.operands()).map(|operand| operand.to_string()).collect();
+    assert_eq!(operands, vec![
+        "file", "1", "FILE", "2", "IN", "[1]", "at", "IN", "f", "INTO", "f", "DEFAULT", "INTO", "FILE", "text", "m", "{TRUE: 1}", "FORMAT", "html",
+    ]);
+    assert!(matches!(&program.statements[4].kind, StatementKind::Call(_, arguments, None) if arguments.len() == 1));
+    assert!(matches!(&program.statements[6].kind, StatementKind::Pager(_, false)));
+    assert!(matches!(&program.statements[7].kind, StatementKind::Pager(_, true)));
+
+    let errors = parse("NUM TRUE 1; FORMAT x; FOR x INTO xs").unwrap_err();
+    let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+    assert_eq!(messages, vec![
+        "1:5: error: Improper operand provided to NUM operator: expected a variable, found `TRUE`",
+        "1:13: error: Expected an operator, found `FORMAT`",
+        "1:29: error: Expected IN after the variable of FOR, found `INTO`",
+    ]);
+}
@@ -0,0 +1,99 @@
+use crate::backend::Backend;
+
+// The frames a spinner goes through, one every FRAME_MS milliseconds
+pub const FRAMES: [char; 4] = ['|', '/', '-', '\\'];
+pub const FRAME_MS: u64 = 100;
+
+// Draws a frame of a spinner and its label at a position, leaving the cursor there
+pub fn spinner(backend: &mut dyn Backend, at: (i32, i32), frame: usize, label: &str) {
+    backend.move_cursor(at.0, at.1);
+    backend.print(&format!("{} {}", FRAMES[frame % FRAMES.len()], label));
+    backend.move_cursor(at.0, at.1);
+    backend.refresh();
+}
+
+// Draws a progress bar with its label and percentage, as in `Copying [#####-----]  50%`, filling
+// the rest of the line from a position and leaving the cursor there
+pub fn gauge(backend: &mut dyn Backend, at: (i32, i32), label: &str, percent: i32) {
+    let percent = percent.clamp(0, 100);
+    let room = (backend.size().1 - at.1) as usize;
+    let label = if label.is_empty() { String::new() } else { format!("{} ", label) };
+    // The brackets, a space and the percentage take 7 columns
+    let width = room.saturating_sub(label.chars().count() + 7).max(10);
+    let filled = width * percent as usize / 100;
+
+    backend.move_cursor(at.0, at.1);
+    backend.print(&format!("{}[{}{}] {:>3}%", label, "#".repeat(filled), "-".repeat(width - filled), percent));
+    backend.move_cursor(at.0, at.1);
+    backend.refresh();
+}
+
+// Blanks what a spinner or gauge drew
+pub fn clear(backend: &mut dyn Backend, at: (i32, i32), columns: usize) {
+    backend.move_cursor(at.0, at.1);
+    backend.print(&" ".repeat(columns));
+    backend.move_cursor(at.0, at.1);
+    backend.refresh();
+}
+
+// Follows the output of a command the way `dialog --gauge` reads it: a line holding a number is
+// the new percentage, and the lines between two `XXX` lines are a percentage followed by a new
+// label. Returns whether the line changed anything.
+#[derive(Eq, PartialEq, Debug, Clone, Default)]
+pub struct GaugeInput {
+    pub percent: i32,
+    pub label: Option<String>,
+    block: Option<Vec<String>>, // The lines of an unfinished XXX block
+}
+
+impl GaugeInput {
+    pub fn line(&mut self, line: &str) -> bool {
+        let line = line.trim();
+        if line == "XXX" {
+            return match self.block.take() {
+                Some(lines) => {
+                    let mut lines = lines.into_iter();
+                    if let Some(percent) = lines.next().and_then(|first| first.parse().ok()) {
+                        self.percent = percent;
+                    }
+                    let label: Vec<String> = lines.collect();
+                    if !label.is_empty() {
+                        self.label = Some(label.join(" "));
+                    }
+                    true
+                },
+                None => {
+                    self.block = Some(Vec::new());
+                    false
+                },
+            };
+        }
+
+        match (&mut self.block, line.parse::<i32>()) {
+            (Some(lines), _) => {
+                lines.push(line.to_owned());
+                false
+            },
+            (None, Ok(percent)) => {
+                self.percent = percent;
+                true
+            },
+            (None, Err(_)) => false,
+        }
+    }
+}
+
+#[test]
+fn gauge_test() {
+    use crate::backend::VirtualScreen;
+
+    let mut screen = VirtualScreen::new(3, 30);
+    gauge(&mut screen, (1, 2), "Copy", 50);
+    assert_eq!(screen.row(1), "  Copy [########--------]  50%");
+    assert_eq!(screen.cursor(), (1, 2));
+
+    let mut input = GaugeInput::default();
+    let changed: Vec<bool> = ["10", "noise", "XXX", "40", "Copying", "files", "XXX", "120"].iter().map(|line| input.line(line)).collect();
+    assert_eq!(changed, vec![true, false, false, false, false, false, true, true]);
+    assert_eq!((input.percent, input.label.as_deref()), (120, Some("Copying files")));
+}
@@ -0,0 +1,229 @@
+use crate::backend::{Attribute, Backend, Key};
+use crate::include;
+use crate::interpreter::{self, Interpreter};
+use crate::parser::Program;
+use crate::trace;
+
+// Lines of the terminal given to the REPL: only the bottom one, for the prompt
+pub const PANE_LINES: i32 = 1;
+
+const PROMPT: &str = "> ";
+// Ends every part of a message but the last, when it has to be shown a part at a time
+const MORE: &str = " [Tab]";
+const HELP: &str = "Statements run as soon as Enter is pressed. :vars lists the variables, :quit leaves; Up and Down go through the history";
+
+pub struct Repl {
+    pub pane: Box<dyn Backend>,
+    history: Vec<String>,
+    message: String,
+    page: usize, // The part of the message shown, for messages wider than the prompt line
+}
+
+impl Repl {
+    pub fn new(pane: Box<dyn Backend>) -> Repl {
+        Repl {
+            pane,
+            history: Vec::new(),
+            message: HELP.to_owned(),
+            page: 0,
+        }
+    }
+
+    // Runs entries against the state until :quit, or until no more input can arrive
+    pub fn run(&mut self, state: &mut Interpreter) {
+        loop {
+            state.backend.refresh();
+            self.draw_prompt("");
+            let entry = match self.read_line(state) {
+                Some(entry) => entry,
+                None => break,
+            };
+
+            let entry = entry.trim();
+            if entry.is_empty() {
+                continue;
+            }
+            if self.history.last().map(|last| last.as_str()) != Some(entry) {
+                self.history.push(entry.to_owned());
+            }
+
+            match entry {
+                ":q" | ":quit" => break,
+                ":vars" => {
+                    self.message = trace::variables(&state.variables);
+                },
+                ":help" => self.message = HELP.to_owned(),
+                _ if entry.starts_with(':') => self.message = format!("Unknown command `{}`; try :help", entry),
+                // A statement that fails is reported here rather than ending the session
+                _ => self.message = execute(entry, state),
+            }
+            self.page = 0;
+        }
+    }
+
+    // The message of the last entry waits dimmed after the prompt until something is typed
+    fn draw_prompt(&mut self, buffer: &str) {
+        let (lines, cols) = self.pane.size();
+        let width = (cols - 1).max(0) as usize;
+        let line = format!("{}{}", PROMPT, buffer);
+        let hint = if buffer.is_empty() {
+            let pages = pages(&self.message, width.saturating_sub(PROMPT.len()));
+            pages[self.page % pages.len()].clone()
+        } else {
+            String::new()
+        };
+
+        self.pane.move_cursor(lines - 1, 0);
+        self.pane.print(&line);
+        self.pane.attribute_on(Attribute::Dim);
+        self.pane.print(&hint);
+        self.pane.attribute_off(Attribute::Dim);
+        let used = line.chars().count() + hint.chars().count();
+        self.pane.print(&" ".repeat(width.saturating_sub(used)));
+        self.pane.move_cursor(lines - 1, line.chars().count() as i32);
+        self.pane.refresh();
+    }
+
+    // The terminal is divided again, and the program hears of the resize as it would without the REPL
+    fn resize(&mut self, state: &mut Interpreter) {
+        let (lines, cols) = self.pane.terminal_size();
+        state.backend.place(lines - PANE_LINES, cols, 0, 0);
+        self.pane.place(PANE_LINES, cols, lines - PANE_LINES, 0);
+
+        if let Err(e) = interpreter::handle_resize(state) {
+            self.message = e.to_string();
+            self.page = 0;
+        }
+        state.backend.refresh();
+    }
+
+    fn read_line(&mut self, state: &mut Interpreter) -> Option<String> {
+        self.pane.set_cursor_visible(true);
+
+        let mut buffer = String::new();
+        // Where Up and Down have got to in the history; the end is the line being typed
+        let mut position = self.history.len();
+        loop {
+            match self.pane.read_key() {
+                Key::Enter => break,
+                Key::Eof => return None,
+                Key::Backspace => {
+                    buffer.pop();
+                },
+                Key::Up if position > 0 => {
+                    position -= 1;
+                    buffer = self.history[position].clone();
+                },
+                Key::Down if position < self.history.len() => {
+                    position += 1;
+                    buffer = self.history.get(position).cloned().unwrap_or_default();
+                },
+                // Tab goes through the parts of a message too wide for the line
+                Key::Tab if buffer.is_empty() => {
+                    self.page += 1;
+                },
+                Key::Char(c) => {
+                    buffer.push(c);
+                },
+                Key::Resize => self.resize(state),
+                _ => continue,
+            }
+            self.draw_prompt(&buffer);
+        }
+
+        self.pane.set_cursor_visible(false);
+        Some(buffer)
+    }
+}
+
+// Splits a message into parts that fit in width columns, each but the last ending in MORE
+fn pages(message: &str, width: usize) -> Vec<String> {
+    let chars: Vec<char> = message.chars().collect();
+    if chars.len() <= width {
+        return vec![message.to_owned()];
+    }
+
+    let size = width.saturating_sub(MORE.len()).max(1);
+    let count = chars.len().div_ceil(size);
+    chars.chunks(size).enumerate().map(|(i, chunk)| {
+        let page: String = chunk.iter().collect();
+        if i + 1 < count { page + MORE } else { page }
+    }).collect()
+}
+
+// Runs one entry and describes what came of it: the variables it changed, or what went wrong
+fn execute(entry: &str, state: &mut Interpreter) -> String {
+    // Entries include files relative to the current directory
+    let program = match Program::parse(entry).and_then(|program| include::expand(program, None)) {
+        Ok(program) => program,
+        Err(e) => return e.to_string().replace('\n', "; "),
+    };
+
+    let before = state.variables.clone();
+    match interpreter::execute(&program.statements, state) {
+        Ok(()) => trace::changes(&before, &state.variables),
+        Err(e) => e.to_string(),
+    }
+}
+
+#[test]
+fn repl_test() {
+    use crate::backend::VirtualScreen;
+
+    let mut pane = VirtualScreen::new(PANE_LINES, 60);
+    pane.push_str("CLR c 0 0 1000\nMOVE 2 1; PRINT \"hi\"\n");
+    // Up twice brings back the first entry, which is changed before it runs
+    pane.push_keys(vec![Key::Up, Key::Up, Key::Backspace]);
+    pane.push_str("\nNUM nope 1\n:vars\n:quit\nPRINT \"never\"\n");
+
+    let mut screen = VirtualScreen::new(10, 40);
+    let mut state = Interpreter::new(&mut screen);
+    let mut repl = Repl::new(Box::new(pane));
+    repl.run(&mut state);
+
+    assert_eq!(repl.history, vec!["CLR c 0 0 1000", "MOVE 2 1; PRINT \"hi\"", "CLR c 0 0 100", "NUM nope 1", ":vars", ":quit"]);
+    assert!(repl.message.starts_with("$=39 G=9 args=[] background=rgb(0, 0, 0) c=rgb(0, 0, 100) foreground=rgb(1000, 1000, 1000)"));
+    drop(state);
+    assert_eq!(screen.row(2), " hi");
+}
+
+#[test]
+fn repl_error_test() {
+    let mut screen = crate::backend::VirtualScreen::new(10, 40);
+    let mut state = Interpreter::new(&mut screen);
+    assert_eq!(execute("MOVE 1 2", &mut state), "x=2 y=1");
+    assert_eq!(execute("MOVE 1", &mut state), "1:1: error: Missing operand for MOVE operator");
+    assert_eq!(execute("NUM nope 1", &mut state), "1:5: error: Improper operand provided to NUM operator");
+}
+
+#[test]
+fn repl_resize_test() {
+    use crate::backend::VirtualScreen;
+
+    let mut pane = VirtualScreen::new(PANE_LINES, 30);
+    pane.push_str("ON RESIZE; MOVE G 0; PRINT \"resized\"; END\n");
+    // The pane stands for the whole terminal, which grows to 8 lines of 50 columns
+    pane.resize(8, 50);
+    pane.push_str("CLR c 0 0 1000\n:vars\n");
+    // Tab shows the second part of the variables
+    pane.push_keys(vec![Key::Tab]);
+
+    let mut screen = VirtualScreen::new(10, 40);
+    let mut state = Interpreter::new(&mut screen);
+    let mut repl = Repl::new(Box::new(pane));
+    repl.run(&mut state);
+
+    assert_eq!(repl.pane.size(), (PANE_LINES, 50));
+    assert_eq!(repl.pane.snapshot().row(0), "> c=rgb(0, 0, 1000) foreground=rgb(1000, 10 [Tab]");
+    assert_eq!(state.variables.get("G"), Some(&interpreter::Variable::Num(6)));
+    assert_eq!(state.variables.get("$"), Some(&interpreter::Variable::Num(49)));
+    drop(state);
+    assert_eq!(screen.size(), (7, 50));
+    assert_eq!(screen.row(6), "resized");
+}
+
+#[test]
+fn pages_test() {
+    assert_eq!(pages("short", 10), vec!["short"]);
+    assert_eq!(pages("a message too long", 10), vec!["a me [Tab]", "ssag [Tab]", "e to [Tab]", "o lo [Tab]", "ng"]);
+}
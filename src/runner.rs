@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::backend::VirtualScreen;
+use crate::check;
+use crate::error::PzxError;
+use crate::include;
+use crate::interpreter::{Interpreter, Variable};
+use crate::keys::{parse_keys, Replay};
+use crate::parser::Program;
+use crate::snapshot::{self, Format};
+
+// The expectation files a script can have, by extension
+const EXPECTATIONS: [&str; 3] = ["screen", "out", "vars"];
+
+// What a script left behind, in the form its expectation files store it
+struct Outcome {
+    screen: String,
+    out: String,
+    vars: String,
+}
+
+// How a script fared
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum Status {
+    Passed,
+    Blessed,
+    Failed(Vec<Failure>),
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum Failure {
+    // The script could not be read or run, or has nothing to be compared with
+    Error(String),
+    Mismatch { path: PathBuf, expected: String, actual: String },
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct TestResult {
+    pub name: String,
+    pub status: Status,
+}
+
+// Runs every `name.pzx` in a directory against a virtual screen, replaying `name.keys` if it
+// exists, and compares the final screen with `name.screen`, the printed `out` with `name.out` and
+// the variables it set with `name.vars`. A script without any of them fails, since nothing would
+// be checked. With `bless` the expectation files are written instead.
+pub fn run_tests(dir: &Path, bless: bool, size: (i32, i32)) -> Result<Vec<TestResult>, String> {
+    let mut scripts: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|e| format!("Could not read {}: {}", dir.display(), e))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().map(|ext| ext == "pzx").unwrap_or(false))
+        .collect();
+    scripts.sort();
+
+    if scripts.is_empty() {
+        return Err(format!("No .pzx scripts found in {}", dir.display()));
+    }
+
+    let mut res = Vec::new();
+    for script in scripts.iter() {
+        let name = script.file_name().unwrap().to_string_lossy().into_owned();
+        let status = match run_script(script, size) {
+            Ok(outcome) => compare(script, &outcome, bless)?,
+            Err(e) => Status::Failed(vec![Failure::Error(e)]),
+        };
+        res.push(TestResult { name, status });
+    }
+    Ok(res)
+}
+
+fn compare(script: &Path, outcome: &Outcome, bless: bool) -> Result<Status, String> {
+    let actuals = [&outcome.screen, &outcome.out, &outcome.vars];
+    let mut failures = Vec::new();
+    let mut checked = false;
+    for (extension, actual) in EXPECTATIONS.iter().zip(actuals.iter()) {
+        let path = script.with_extension(extension);
+        if bless {
+            fs::write(&path, actual).map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+        } else if path.is_file() {
+            checked = true;
+            let expected = fs::read_to_string(&path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+            if expected != **actual {
+                failures.push(Failure::Mismatch { path, expected, actual: actual.to_string() });
+            }
+        }
+    }
+
+    if bless {
+        Ok(Status::Blessed)
+    } else if !checked {
+        Ok(Status::Failed(vec![Failure::Error("No .screen, .out or .vars file to compare with; run with --bless to create them".to_owned())]))
+    } else if failures.is_empty() {
+        Ok(Status::Passed)
+    } else {
+        Ok(Status::Failed(failures))
+    }
+}
+
+pub fn passed(results: &[TestResult]) -> bool {
+    results.iter().all(|result| !matches!(result.status, Status::Failed(_)))
+}
+
+// The results the way `cargo test` shows them, with a diff for every mismatch
+pub fn report(results: &[TestResult], color: bool) -> String {
+    let mut res = String::new();
+    for result in results.iter() {
+        let status = match &result.status {
+            Status::Passed => paint("ok", "32", color),
+            Status::Blessed => paint("blessed", "32", color),
+            Status::Failed(_) => paint("FAILED", "31", color),
+        };
+        res.push_str(&format!("test {} ... {}\n", result.name, status));
+        if let Status::Failed(failures) = &result.status {
+            for failure in failures.iter() {
+                match failure {
+                    Failure::Error(e) => res.push_str(&format!("    {}\n", e)),
+                    Failure::Mismatch { path, expected, actual } => res.push_str(&render_diff(path, expected, actual, color)),
+                }
+            }
+        }
+    }
+
+    let failed = results.iter().filter(|result| matches!(result.status, Status::Failed(_))).count();
+    let summary = if failed == 0 { paint("ok", "32", color) } else { paint("FAILED", "31", color) };
+    res.push_str(&format!("\ntest result: {}. {} passed; {} failed\n", summary, results.len() - failed, failed));
+    res
+}
+
+fn run_script(path: &Path, size: (i32, i32)) -> Result<Outcome, String> {
+    let commands = fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+
+    // Without a keys file the script sees no input at all rather than waiting on the keyboard
+    let keys_path = path.with_extension("keys");
+    let keys = if keys_path.is_file() {
+        let raw = fs::read_to_string(&keys_path).map_err(|e| format!("Could not read {}: {}", keys_path.display(), e))?;
+        parse_keys(&raw)?
+    } else {
+        Vec::new()
+    };
+
+    let program = Program::parse(&commands)
+        .and_then(|program| include::expand(program, Some(path)))
+        .map_err(|e| e.to_string().replace('\n', "\n    "))?;
+
+    let mut screen = VirtualScreen::new(size.0, size.1);
+    let mut interpreter = Interpreter::new(&mut screen);
+    interpreter.set_replay(Replay::new(keys, Duration::from_millis(0)));
+    let outcome = interpreter.run(&program).map_err(|e| located(&program, &e))?;
+
+    Ok(Outcome {
+        screen: snapshot::render(&screen, Format::Text),
+        out: format!("{}\n", outcome.out),
+        vars: exported(&outcome.variables),
+    })
+}
+
+// A problem in an included file names the file, as it would when the script runs on its own
+fn located(program: &Program, e: &PzxError) -> String {
+    let lines: Vec<String> = e.diagnostics().iter().map(|diagnostic| match program.file(diagnostic.span) {
+        Some(file) => format!("{}:{}", file, diagnostic),
+        None => diagnostic.to_string(),
+    }).collect();
+    lines.join("\n    ")
+}
+
+// The variables a script set, one `name = value` per line in order of their names. Built-in
+// variables depend on the screen rather than the script, and `out` has a file of its own.
+fn exported(variables: &HashMap<String, Variable>) -> String {
+    let mut names: Vec<&String> = variables.keys().filter(|name| *name != "out" && check::built_in(name).is_none()).collect();
+    names.sort();
+    names.iter().map(|name| format!("{} = {}\n", name, variables[*name])).collect()
+}
+
+fn paint(text: &str, code: &str, color: bool) -> String {
+    if color {
+        format!("\x1b[{}m{}\x1b[0m", code, text)
+    } else {
+        text.to_owned()
+    }
+}
+
+fn render_diff(path: &Path, expected: &str, actual: &str, color: bool) -> String {
+    let mut res = format!("    --- {} (expected)\n    +++ actual\n", path.display());
+    for (sign, line) in diff_lines(expected, actual) {
+        let line = format!("    {}{}", sign, line);
+        res.push_str(&match sign {
+            '-' => paint(&line, "31", color),
+            '+' => paint(&line, "32", color),
+            _ => line,
+        });
+        res.push('\n');
+    }
+    res
+}
+
+// A line diff through the longest common subsequence; screens are small enough for the table
+fn diff_lines<'a>(expected: &'a str, actual: &'a str) -> Vec<(char, &'a str)> {
+    let old: Vec<&str> = expected.lines().collect();
+    let new: Vec<&str> = actual.lines().collect();
+
+    let mut common = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut res = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            res.push((' ', old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+            res.push(('-', old[i]));
+            i += 1;
+        } else {
+            res.push(('+', new[j]));
+            j += 1;
+        }
+    }
+    res
+}
+
+#[test]
+fn run_tests_test() {
+    let dir = std::env::temp_dir().join(format!("pzx-runner-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("greet.pzx"), "PRINT \"Name: \";\nAWAIT out;\nLET count = 1;\n").unwrap();
+    fs::write(dir.join("greet.keys"), "John<Enter>").unwrap();
+
+    // Nothing to compare with is a failure rather than a pass
+    let results = run_tests(&dir, false, (4, 20)).unwrap();
+    assert!(!passed(&results));
+    assert!(report(&results, false).contains("No .screen, .out or .vars file"));
+
+    // Blessing records the results, which the next run then matches
+    assert!(passed(&run_tests(&dir, true, (4, 20)).unwrap()));
+    assert_eq!(fs::read_to_string(dir.join("greet.screen")).unwrap(), "Name: John\n");
+    assert_eq!(fs::read_to_string(dir.join("greet.out")).unwrap(), "John\n");
+    assert_eq!(fs::read_to_string(dir.join("greet.vars")).unwrap(), "count = 1\n");
+    assert!(passed(&run_tests(&dir, false, (4, 20)).unwrap()));
+
+    fs::write(dir.join("greet.vars"), "count = 2\n").unwrap();
+    let results = run_tests(&dir, false, (4, 20)).unwrap();
+    assert!(!passed(&results));
+    assert!(report(&results, false).contains("    -count = 2\n    +count = 1\n"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn diff_lines_test() {
+    assert_eq!(diff_lines("a\nb\nc", "a\nx\nc"), vec![(' ', "a"), ('-', "b"), ('+', "x"), (' ', "c")]);
+}
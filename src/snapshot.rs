@@ -0,0 +1,174 @@
+use std::fs;
+use std::io;
+
+use crate::backend::{Attribute, Backend, Cell, VirtualScreen};
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Format {
+    Text,
+    Ansi,
+    Html,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name.to_lowercase().as_str() {
+            "text" | "txt" => Some(Format::Text),
+            "ansi" => Some(Format::Ansi),
+            "html" => Some(Format::Html),
+            _ => None,
+        }
+    }
+
+    // Guesses the format from a file extension, falling back to plain text
+    pub fn from_path(path: &str) -> Format {
+        let extension = path.rsplit('.').next().unwrap_or("").to_lowercase();
+        match extension.as_str() {
+            "html" | "htm" => Format::Html,
+            "ansi" | "ans" => Format::Ansi,
+            _ => Format::Text,
+        }
+    }
+}
+
+pub fn write(screen: &VirtualScreen, path: &str, format: Format) -> io::Result<()> {
+    fs::write(path, render(screen, format))
+}
+
+pub fn render(screen: &VirtualScreen, format: Format) -> String {
+    match format {
+        Format::Text => {
+            let mut res = screen.text();
+            res.push('\n');
+            res
+        },
+        Format::Ansi => render_ansi(screen),
+        Format::Html => render_html(screen),
+    }
+}
+
+// Colors in pzx go from 0 to 1000; everything else expects 0 to 255
+fn to_rgb(color: (i16, i16, i16)) -> (i32, i32, i32) {
+    let scale = |c: i16| (c.clamp(0, 1000) as i32 * 255 + 500) / 1000;
+    (scale(color.0), scale(color.1), scale(color.2))
+}
+
+// The colors and attributes a cell is drawn with; runs of equal styles share one escape or span
+#[derive(Eq, PartialEq, Clone, Copy)]
+struct Style {
+    foreground: (i32, i32, i32),
+    background: (i32, i32, i32),
+    attributes: u8,
+}
+
+fn style(screen: &VirtualScreen, y: i32, x: i32, cell: &Cell) -> Style {
+    let (foreground, background) = screen.cell_colors(y, x);
+    let (mut foreground, mut background) = (to_rgb(foreground), to_rgb(background));
+    if cell.has(Attribute::Reverse) {
+        std::mem::swap(&mut foreground, &mut background);
+    }
+    Style {
+        foreground,
+        background,
+        attributes: cell.attributes,
+    }
+}
+
+fn render_ansi(screen: &VirtualScreen) -> String {
+    let mut res = String::new();
+    let (lines, cols) = screen.size();
+
+    for y in 0..lines {
+        let mut current: Option<Style> = None;
+        for x in 0..cols {
+            let cell = screen.cell(y, x);
+            let style = style(screen, y, x, &cell);
+            if current != Some(style) {
+                res.push_str("\x1b[0");
+                for (attribute, code) in &[(Attribute::Bold, 1), (Attribute::Dim, 2), (Attribute::Underline, 4), (Attribute::Blink, 5)] {
+                    if cell.has(*attribute) {
+                        res.push_str(&format!(";{}", code));
+                    }
+                }
+                let (fr, fg, fb) = style.foreground;
+                let (br, bg, bb) = style.background;
+                res.push_str(&format!(";38;2;{};{};{};48;2;{};{};{}m", fr, fg, fb, br, bg, bb));
+                current = Some(style);
+            }
+            res.push(cell.ch);
+        }
+        res.push_str("\x1b[0m\n");
+    }
+
+    res
+}
+
+fn render_html(screen: &VirtualScreen) -> String {
+    let (lines, cols) = screen.size();
+    let (_, background) = screen.pair(screen.cell(0, 0).pair);
+    let (r, g, b) = to_rgb(screen.color(background));
+
+    let mut res = format!("<pre class=\"pzx-snapshot\" style=\"background-color: #{:02x}{:02x}{:02x}\">\n", r, g, b);
+    for y in 0..lines {
+        let mut current: Option<Style> = None;
+        for x in 0..cols {
+            let cell = screen.cell(y, x);
+            let style = style(screen, y, x, &cell);
+            if current != Some(style) {
+                if current.is_some() {
+                    res.push_str("</span>");
+                }
+                res.push_str(&html_span(&style));
+                current = Some(style);
+            }
+            match cell.ch {
+                '&' => res.push_str("&amp;"),
+                '<' => res.push_str("&lt;"),
+                '>' => res.push_str("&gt;"),
+                c => res.push(c),
+            }
+        }
+        res.push_str("</span>\n");
+    }
+    res.push_str("</pre>\n");
+
+    res
+}
+
+fn html_span(style: &Style) -> String {
+    let (fr, fg, fb) = style.foreground;
+    let (br, bg, bb) = style.background;
+    let mut css = format!("color: #{:02x}{:02x}{:02x}; background-color: #{:02x}{:02x}{:02x}", fr, fg, fb, br, bg, bb);
+
+    let has = |attribute: Attribute| style.attributes & attribute.bit() != 0;
+    if has(Attribute::Bold) {
+        css.push_str("; font-weight: bold");
+    }
+    if has(Attribute::Dim) {
+        css.push_str("; opacity: 0.6");
+    }
+    if has(Attribute::Underline) {
+        css.push_str("; text-decoration: underline");
+    }
+
+    format!("<span style=\"{}\">", css)
+}
+
+#[test]
+fn render_test() {
+    let mut screen = VirtualScreen::new(2, 4);
+    screen.define_color(16, 1000, 0, 0);
+    screen.define_color(17, 0, 0, 0);
+    screen.define_pair(1, 16, 17);
+    screen.use_pair(1);
+    screen.print("<a>");
+    screen.attribute_on(Attribute::Bold);
+    screen.print("b");
+
+    assert_eq!(render(&screen, Format::Text), "<a>b\n");
+    assert!(render(&screen, Format::Ansi).starts_with("\x1b[0;38;2;255;0;0;48;2;0;0;0m<a>\x1b[0;1;38;2;255;0;0;48;2;0;0;0mb\x1b[0m\n"));
+    assert!(render(&screen, Format::Html).contains("&lt;a&gt;</span><span style=\"color: #ff0000; background-color: #000000; font-weight: bold\">b</span>"));
+
+    assert_eq!(Format::from_path("docs/menu.HTML"), Format::Html);
+    assert_eq!(Format::from_path("screen"), Format::Text);
+}
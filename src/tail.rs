@@ -0,0 +1,191 @@
+use std::collections::VecDeque;
+
+use crate::backend::{Attribute, Backend, Key};
+
+// How many lines are kept for scrolling back; older ones are dropped
+pub const SCROLLBACK: usize = 1000;
+
+// The lines TAIL shows in the part of the screen from a line down, above a status line. It
+// follows new lines until the user scrolls back, and can show only the lines holding a filter.
+// While following, that part of the screen is a scrolling region, so that new lines scroll the
+// ones above them up rather than the whole view being drawn again.
+pub struct Tail {
+    pub lines: VecDeque<String>,
+    top: i32,
+    offset: usize, // How many of the shown lines are below the view; 0 follows new lines
+    filter: String,
+    editing: bool, // Whether keys go to the filter
+    fresh: usize, // Shown lines that arrived since the view was drawn
+    drawn: Option<usize>, // The height the view was drawn at, or None when it has to be drawn again
+}
+
+impl Tail {
+    pub fn new(top: i32) -> Tail {
+        Tail {
+            lines: VecDeque::new(),
+            top,
+            offset: 0,
+            filter: String::new(),
+            editing: false,
+            fresh: 0,
+            drawn: None,
+        }
+    }
+
+    // A view that was scrolled back stays on the same lines
+    pub fn push(&mut self, line: String) {
+        if self.lines.len() == SCROLLBACK {
+            self.lines.pop_front();
+            if self.offset > 0 {
+                self.drawn = None;
+            }
+        }
+        if line.contains(self.filter.as_str()) {
+            if self.offset > 0 {
+                self.offset += 1;
+            } else {
+                self.fresh += 1;
+            }
+        }
+        self.lines.push_back(line.replace('\t', "    "));
+    }
+
+    // The lines of the region, leaving the last one for the status line
+    pub fn height(&self, backend: &dyn Backend) -> usize {
+        (backend.size().0 - self.top - 1).max(1) as usize
+    }
+
+    fn shown(&self) -> Vec<&String> {
+        self.lines.iter().filter(|line| line.contains(self.filter.as_str())).collect()
+    }
+
+    // The view is drawn again in full next time, as after a resize
+    pub fn redraw(&mut self) {
+        self.drawn = None;
+    }
+
+    // Returns true once the user asks to go back to the script
+    pub fn key(&mut self, key: Key, page: usize) -> bool {
+        self.drawn = None;
+        if self.editing {
+            match key {
+                Key::Char(c) => self.filter.push(c),
+                Key::Backspace => {
+                    self.filter.pop();
+                },
+                Key::Enter => self.editing = false,
+                Key::Escape => {
+                    self.filter.clear();
+                    self.editing = false;
+                },
+                _ => {},
+            }
+            self.offset = 0;
+            return false;
+        }
+
+        let last = self.shown().len().saturating_sub(page);
+        match key {
+            Key::Char('q') => return true,
+            Key::Char('/') => self.editing = true,
+            Key::PageUp => self.offset = (self.offset + page).min(last),
+            Key::PageDown => self.offset = self.offset.saturating_sub(page),
+            Key::Up => self.offset = (self.offset + 1).min(last),
+            Key::Down => self.offset = self.offset.saturating_sub(1),
+            Key::Home => self.offset = last,
+            Key::End => self.offset = 0,
+            _ => {},
+        }
+        false
+    }
+
+    pub fn render(&mut self, backend: &mut dyn Backend) {
+        let width = (backend.size().1 - 1).max(0) as usize;
+        let height = self.height(backend);
+        let shown = self.shown();
+        let end = shown.len() - self.offset.min(shown.len());
+        let start = end.saturating_sub(height);
+
+        // Lines arriving below a full view scroll it up; anything else draws it again
+        backend.set_scroll_region(Some((self.top, self.top + height as i32 - 1)));
+        if self.drawn == Some(height) && self.fresh < height && shown.len() >= height + self.fresh {
+            if self.fresh > 0 {
+                backend.scroll(self.fresh as i32);
+            }
+            for row in height - self.fresh..height {
+                backend.move_cursor(self.top + row as i32, 0);
+                backend.print(&fit(shown[start + row], width));
+            }
+        } else {
+            for row in 0..height {
+                let line = shown.get(start + row).map(|line| line.as_str()).unwrap_or("");
+                backend.move_cursor(self.top + row as i32, 0);
+                backend.print(&fit(line, width));
+            }
+        }
+
+        let position = if self.offset == 0 { "following".to_owned() } else { format!("{} lines back", self.offset) };
+        let status = if self.editing {
+            format!(" /{}", self.filter)
+        } else if self.filter.is_empty() {
+            format!(" {} lines, {}  q back  / filter  PgUp PgDn scroll", self.lines.len(), position)
+        } else {
+            format!(" {} of {} lines holding \"{}\", {}  q back  / filter  PgUp PgDn scroll", shown.len(), self.lines.len(), self.filter, position)
+        };
+        backend.move_cursor(self.top + height as i32, 0);
+        backend.attribute_on(Attribute::Reverse);
+        backend.print(&fit(&status, width));
+        backend.attribute_off(Attribute::Reverse);
+
+        backend.set_cursor_visible(self.editing);
+        if self.editing {
+            backend.move_cursor(self.top + height as i32, 2 + self.filter.chars().count() as i32);
+        }
+        backend.refresh();
+        self.fresh = 0;
+        self.drawn = Some(height);
+    }
+}
+
+// Cuts a line to the width of the screen, or pads it to clear what was there before
+fn fit(text: &str, width: usize) -> String {
+    let res: String = text.chars().take(width).collect();
+    format!("{:<1$}", res, width)
+}
+
+#[test]
+fn tail_test() {
+    use crate::backend::VirtualScreen;
+
+    let mut screen = VirtualScreen::new(5, 30);
+    let mut tail = Tail::new(1);
+    for i in 0..6 {
+        tail.push(format!("line {}", i));
+    }
+    tail.render(&mut screen);
+    assert_eq!(screen.row(1).trim_end(), "line 3");
+    assert_eq!(screen.row(4).trim_end(), " 6 lines, following  q back");
+
+    // A new line scrolls the view up, carrying along whatever else was on it, and is printed at
+    // the bottom
+    screen.move_cursor(2, 28);
+    screen.print("*");
+    tail.push("line 6".to_owned());
+    tail.render(&mut screen);
+    assert_eq!(screen.row(1), "line 4                      *");
+    assert_eq!(screen.row(3).trim_end(), "line 6");
+
+    // Scrolling back keeps the view on the same lines while new ones arrive
+    assert!(!tail.key(Key::PageUp, 3));
+    tail.push("line 7".to_owned());
+    tail.render(&mut screen);
+    assert_eq!(screen.row(1).trim_end(), "line 1");
+
+    for key in [Key::Char('/'), Key::Char('5'), Key::Enter].iter() {
+        tail.key(*key, 3);
+    }
+    tail.render(&mut screen);
+    assert_eq!(screen.row(1).trim_end(), "line 5");
+    assert!(screen.row(4).starts_with(" 1 of 8 lines holding \"5\""));
+    assert!(tail.key(Key::Char('q'), 3));
+}
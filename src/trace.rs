@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Write};
+
+use crate::interpreter::Variable;
+use crate::backend::{Attribute, Backend, Key, VirtualScreen};
+use crate::parser::{Field, FieldKind, Operand, Statement, StatementKind};
+
+// How much goes into the trace: every executed statement at Info, and at Debug also the colors
+// and pairs handed to the terminal and every key read
+#[derive(Eq, PartialEq, Debug, Clone, Copy, PartialOrd, Ord)]
+pub enum LogLevel {
+    Info,
+    Debug,
+}
+
+impl LogLevel {
+    pub fn from_name(name: &str) -> Option<LogLevel> {
+        match name {
+            "info" => Some(LogLevel::Info),
+            "debug" => Some(LogLevel::Debug),
+            _ => None,
+        }
+    }
+}
+
+// Lines are written straight to the file rather than buffered, so that everything up to a
+// crash is kept
+pub struct Trace {
+    file: File,
+    level: LogLevel,
+}
+
+impl Trace {
+    pub fn create(path: &str, level: LogLevel) -> io::Result<Trace> {
+        Ok(Trace {
+            file: File::create(path)?,
+            level,
+        })
+    }
+
+    // Both handles write to the same file
+    pub fn try_clone(&self) -> io::Result<Trace> {
+        Ok(Trace {
+            file: self.file.try_clone()?,
+            level: self.level,
+        })
+    }
+
+    pub fn info(&self, line: &str) {
+        self.log(LogLevel::Info, line);
+    }
+
+    pub fn debug(&self, line: &str) {
+        self.log(LogLevel::Debug, line);
+    }
+
+    fn log(&self, level: LogLevel, line: &str) {
+        if level <= self.level {
+            writeln!(&self.file, "{}", line).expect("Err: Could not write trace");
+        }
+    }
+}
+
+// A statement with the values its operands had when it ran, followed by every variable it
+// changed, as in `1:1 MOVE 3 10 -> x=10 y=3`
+pub fn statement(statement: &Statement, before: &HashMap<String, Variable>, after: &HashMap<String, Variable>) -> String {
+    let mut res = format!("{} {}", statement.span, statement.operator.name());
+    for (i, operand) in statement.kind.operands().iter().enumerate() {
+        res.push(' ');
+        match operand {
+            Operand::Identifier(name, _) => match before.get(name) {
+                Some(value) if reads(&statement.kind, i) => res.push_str(&value.to_string()),
+                _ => res.push_str(name),
+            },
+            _ => res.push_str(&operand.to_string()),
+        }
+    }
+
+    let changes = changes(before, after);
+    if !changes.is_empty() {
+        res.push_str(" -> ");
+        res.push_str(&changes);
+    }
+    res
+}
+
+// Variables that differ between two points, by name
+pub fn changes(before: &HashMap<String, Variable>, after: &HashMap<String, Variable>) -> String {
+    let mut names: Vec<&String> = after.keys().filter(|name| before.get(*name) != after.get(*name)).collect();
+    names.sort();
+    let changes: Vec<String> = names.iter().map(|name| format!("{}={}", name, after[*name])).collect();
+    changes.join(" ")
+}
+
+// Every variable by name, as in `x=10 y=3`
+pub fn variables(variables: &HashMap<String, Variable>) -> String {
+    let mut names: Vec<&String> = variables.keys().collect();
+    names.sort();
+    let res: Vec<String> = names.iter().map(|name| format!("{}={}", name, variables[*name])).collect();
+    res.join(" ")
+}
+
+// Whether an operand is a value that is read, rather than the name of something to assign or save
+fn reads(kind: &StatementKind, index: usize) -> bool {
+    match kind {
+        StatementKind::Print(_) | StatementKind::Foreground(_) | StatementKind::Background(_) => true,
+        StatementKind::Move(..) | StatementKind::Shift(..) => true,
+        StatementKind::StringAssign(..) | StatementKind::NumberAssign(..) => index == 1,
+        StatementKind::Snapshot(..) => index == 0,
+        StatementKind::Env(..) => index >= 1,
+        StatementKind::Exec(..) | StatementKind::Shell(..) => index == 1,
+        StatementKind::Spin(..) | StatementKind::Gauge(..) => index <= 1,
+        StatementKind::Tail(..) => index == 1,
+        StatementKind::Pager(..) => true,
+        StatementKind::Form(fields, _) => !targets(fields).contains(&index),
+        StatementKind::Call(_, arguments, _) => index >= 1 && index <= arguments.len(),
+        StatementKind::Return(_) | StatementKind::If(..) => true,
+        StatementKind::BoolAssign(..) | StatementKind::Let(..) | StatementKind::Const(..) | StatementKind::Set(..) => index == 1,
+        StatementKind::ListAssign(..) | StatementKind::MapAssign(..) | StatementKind::Push(..) | StatementKind::For(..) => index == 1,
+        _ => false,
+    }
+}
+
+// Where the variables of a FORM are among its operands, each field taking a target, a label,
+// maybe options and maybe a position
+fn targets(fields: &[Field]) -> Vec<usize> {
+    let mut index = 0;
+    fields.iter().map(|field| {
+        let res = index;
+        index += 2 + matches!(field.kind, FieldKind::Choice(_)) as usize + 2 * field.at.is_some() as usize;
+        res
+    }).collect()
+}
+
+// Passes everything through to another backend, noting every color and pair it is given
+pub struct TracingBackend<'a> {
+    pub inner: &'a mut dyn Backend,
+    pub trace: Trace,
+}
+
+impl<'a> Backend for TracingBackend<'a> {
+    fn print(&mut self, text: &str) {
+        self.inner.print(text);
+    }
+
+    fn delete_char(&mut self) {
+        self.inner.delete_char();
+    }
+
+    fn clear(&mut self) {
+        self.inner.clear();
+    }
+
+    fn refresh(&mut self) {
+        self.inner.refresh();
+    }
+
+    fn move_cursor(&mut self, y: i32, x: i32) {
+        self.inner.move_cursor(y, x);
+    }
+
+    fn cursor(&self) -> (i32, i32) {
+        self.inner.cursor()
+    }
+
+    fn size(&self) -> (i32, i32) {
+        self.inner.size()
+    }
+
+    fn set_cursor_visible(&mut self, visible: bool) {
+        self.inner.set_cursor_visible(visible);
+    }
+
+    fn terminal_size(&self) -> (i32, i32) {
+        self.inner.terminal_size()
+    }
+
+    fn place(&mut self, lines: i32, cols: i32, y: i32, x: i32) {
+        self.trace.debug(&format!("place {}x{} at {} {}", lines, cols, y, x));
+        self.inner.place(lines, cols, y, x);
+    }
+
+    fn define_color(&mut self, color: i16, r: i16, g: i16, b: i16) {
+        self.trace.debug(&format!("color {} = {} {} {}", color, r, g, b));
+        self.inner.define_color(color, r, g, b);
+    }
+
+    fn define_pair(&mut self, pair: i16, foreground: i16, background: i16) {
+        self.trace.debug(&format!("pair {} = {} on {}", pair, foreground, background));
+        self.inner.define_pair(pair, foreground, background);
+    }
+
+    fn use_pair(&mut self, pair: i16) {
+        self.trace.debug(&format!("use pair {}", pair));
+        self.inner.use_pair(pair);
+    }
+
+    fn poll_key(&mut self, timeout: i32) -> Option<Key> {
+        self.inner.poll_key(timeout)
+    }
+
+    fn set_scroll_region(&mut self, region: Option<(i32, i32)>) {
+        self.inner.set_scroll_region(region);
+    }
+
+    fn scroll(&mut self, lines: i32) {
+        self.inner.scroll(lines);
+    }
+
+    fn suspend(&mut self) {
+        self.trace.debug("suspend");
+        self.inner.suspend();
+    }
+
+    fn resume(&mut self) {
+        self.trace.debug("resume");
+        self.inner.resume();
+    }
+
+    fn attribute_on(&mut self, attribute: Attribute) {
+        self.inner.attribute_on(attribute);
+    }
+
+    fn attribute_off(&mut self, attribute: Attribute) {
+        self.inner.attribute_off(attribute);
+    }
+
+    fn read_key(&mut self) -> Key {
+        self.inner.read_key()
+    }
+
+    fn snapshot(&self) -> VirtualScreen {
+        self.inner.snapshot()
+    }
+}